@@ -0,0 +1,137 @@
+use std::time::Duration;
+
+use log::{debug, info};
+
+use crate::config::is_dry_run;
+use crate::device::Device;
+use crate::error::DeviceError;
+use crate::transport::{HttpTransport, IsahcTransport, RequestOptions};
+
+/// Sends commands to the relays of an Arduino.
+/// # Example
+///```
+/// use std::time::Duration;
+/// use aa_models::arduino::ArduinoClient;
+///
+/// let client = ArduinoClient::new("10.0.0.5", "porch", Duration::from_secs(2));
+/// assert_eq!("http://10.0.0.5/porch?param=true", client.relay_url(true));
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ArduinoClient {
+    /// The IP address of the Arduino.
+    pub ip: String,
+    /// The endpoint of the relay, see `Device::relay_endpoint`.
+    pub endpoint: String,
+    /// How long to wait for the Arduino to answer.
+    pub timeout: Duration,
+    /// Whether or not the Arduino is served over HTTPS.
+    pub https: bool,
+    /// The options to send requests with, see `Device::request_options`.
+    pub options: RequestOptions,
+}
+
+impl ArduinoClient {
+    /// Creates a client for the given relay of the Arduino at the given IP address, over plain HTTP.
+    pub fn new(ip: &str, endpoint: &str, timeout: Duration) -> ArduinoClient {
+        ArduinoClient {
+            ip: ip.to_string(),
+            endpoint: endpoint.trim_start_matches('/').to_string(),
+            timeout,
+            https: false,
+            options: RequestOptions::default(),
+        }
+    }
+
+    /// Creates a client for the relay of the given device, with the timeout, scheme, auth token and certificate
+    /// option of the device.
+    /// # Example
+    ///```
+    /// use aa_models::arduino::ArduinoClient;
+    /// use aa_models::device::{Device, HardwareType};
+    ///
+    /// let mut device = Device::default();
+    /// device.guid = String::from("porch");
+    /// device.ip = String::from("10.0.0.5");
+    /// assert!(ArduinoClient::for_device(&device).is_none());
+    /// device.hardware = HardwareType::ARDUINO;
+    /// device.relay_index = Some(2);
+    /// device.https = true;
+    /// let client = ArduinoClient::for_device(&device).unwrap();
+    /// assert_eq!("https://10.0.0.5/relay/2?param=false", client.relay_url(false));
+    /// ```
+    /// # Return
+    /// The client, or None if the hardware of the device has no HTTP API (see `HardwareType::supports_http_api`).
+    pub fn for_device(dev: &Device) -> Option<ArduinoClient> {
+        if !dev.hardware.supports_http_api() {
+            return None;
+        }
+        Some(ArduinoClient {
+            ip: dev.ip.clone(),
+            endpoint: dev.relay_endpoint(),
+            timeout: dev.request_timeout(),
+            https: dev.uses_https(),
+            options: dev.request_options(),
+        })
+    }
+
+    /// Gets the URL that sets the relay to the given state.
+    /// # Params
+    /// * `state` Whether the relay should be on or off.
+    pub fn relay_url(&self, state: bool) -> String {
+        let scheme = if self.https { "https" } else { "http" };
+        format!("{}://{}/{}?param={}", scheme, self.ip, self.endpoint, state)
+    }
+
+    /// Sets the relay to the given state.
+    /// # Params
+    /// * `state` Whether the relay should be on or off.
+    /// # Return
+    /// Ok if the Arduino accepted it, `DeviceError::Http` if it answered with an error, `DeviceError::Timeout` if it
+    /// did not answer in time, or `DeviceError::Network` if it could not be reached.
+    pub fn set_relay(&self, state: bool) -> Result<(), DeviceError> {
+        self.set_relay_with_transport(&IsahcTransport, state)
+    }
+
+    /// Sets the given relay to the given state, sending the request through the given transport. See `set_relay`.
+    /// # Example
+    ///```
+    /// use std::time::Duration;
+    /// use isahc::http::StatusCode;
+    /// use aa_models::arduino::ArduinoClient;
+    /// use aa_models::error::DeviceError;
+    /// use aa_models::transport::{HttpResponse, HttpTransport};
+    ///
+    /// struct BrokenRelay;
+    ///
+    /// impl HttpTransport for BrokenRelay {
+    ///     fn get(&self, _url: &str, _timeout: Duration) -> Result<HttpResponse, DeviceError> {
+    ///         Ok(HttpResponse { status: StatusCode::INTERNAL_SERVER_ERROR, body: String::from("relay fault") })
+    ///     }
+    ///
+    ///     fn put_json(&self, _url: &str, _body: Vec<u8>, _timeout: Duration) -> Result<HttpResponse, DeviceError> {
+    ///         unreachable!()
+    ///     }
+    /// }
+    ///
+    /// let client = ArduinoClient::new("10.0.0.5", "porch", Duration::from_secs(2));
+    /// let result = client.set_relay_with_transport(&BrokenRelay, true);
+    /// assert!(matches!(result, Err(DeviceError::Http(StatusCode::INTERNAL_SERVER_ERROR, _))));
+    /// ```
+    pub fn set_relay_with_transport(
+        &self,
+        transport: &dyn HttpTransport,
+        state: bool,
+    ) -> Result<(), DeviceError> {
+        let url = self.relay_url(state);
+        if is_dry_run() {
+            info!("[dry-run] GET {}", url);
+            return Ok(());
+        }
+        debug!("Setting relay with {}", url);
+        let response = transport.get_with_options(&url, self.timeout, &self.options)?;
+        if !response.status.is_success() {
+            return Err(DeviceError::Http(response.status, response.body.trim().to_string()));
+        }
+        Ok(())
+    }
+}
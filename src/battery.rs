@@ -1,8 +1,11 @@
-use isahc::ReadResponseExt;
-use log::debug;
-use serde_json::Value;
+use isahc::prelude::*;
+use isahc::Request;
+use log::{debug, warn};
+use serde::Serialize;
+use serde_json::{json, Value};
 
 use crate::device::Device;
+use crate::error::{deserialize, http_error, network, DeviceError};
 
 /// Checks to see if the given device is a battery, if it is, get the data from the UPS status page.
 /// # Param
@@ -12,17 +15,135 @@ use crate::device::Device;
 pub fn parse_device(mut dev: Device) -> Device {
     if dev.kind == crate::device::DeviceType::BATTERY {
         debug!("Battery IP: {}",dev.ip);
-        let battery_status: Value = serde_json::from_str(
-            isahc::get(format!("http://{}/ups_status.php", dev.ip))
-                .unwrap()
-                .text()
-                .unwrap()
-                .as_str(),
-        )
-            .unwrap();
+        let battery_status = match get_ups_status(&dev) {
+            Ok(status) => status,
+            Err(e) => {
+                // The UPS drops its web server during self-tests, keep what we last knew.
+                warn!("Could not get the UPS status of {}: {}", dev.guid, e);
+                return dev;
+            }
+        };
         dev.last_state = battery_status;
-        dev.database_update();
+        if let Err(e) = dev.database_update() {
+            debug!("Could not update {}: {}", dev.guid, e);
+        }
         return dev.clone();
     }
     dev.clone()
 }
+
+/// Gets the status of the UPS from its status page.
+/// # Param
+/// * dev : The battery/UPS device.
+/// # Return
+/// The status of the UPS, or an error if the page could not be reached or isn't a JSON object (ie an HTML error page).
+fn get_ups_status(dev: &Device) -> Result<Value, DeviceError> {
+    let mut response = Request::get(format!("http://{}/ups_status.php", dev.ip))
+        .timeout(dev.request_timeout())
+        .body(())
+        .map_err(network)?
+        .send()
+        .map_err(http_error)?;
+    let body = response.text().map_err(network)?;
+    if !response.status().is_success() {
+        return Err(DeviceError::Http(response.status(), body));
+    }
+    let status: Value = serde_json::from_str(&body).map_err(deserialize)?;
+    if !status.is_object() {
+        return Err(DeviceError::Deserialize(String::from("UPS status is not an object")));
+    }
+    Ok(status)
+}
+
+/// The status of a UPS, as read from its status page.
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct UpsStatus {
+    /// How charged the battery is, from 0 to 100.
+    pub charge_percent: f32,
+    /// How long the UPS can keep running on battery, in seconds.
+    pub runtime_seconds: u32,
+    /// Whether or not the UPS lost input power and is running on battery.
+    pub on_battery: bool,
+    /// Whether or not the battery is charging.
+    pub charging: bool,
+    /// The voltage of the input power.
+    pub input_voltage: f32,
+}
+
+impl UpsStatus {
+    /// Reads the UPS status out of its status page, which uses the variable names of nut-client (`battery.charge`,
+    /// `battery.runtime`, `ups.status`, `input.voltage`). Numbers may be given as numbers or as strings.
+    /// # Example
+    /// ```
+    /// use aa_models::battery::UpsStatus;
+    /// use serde_json::json;
+    /// let status = UpsStatus::from_value(&json!({
+    ///     "battery.charge": "87",
+    ///     "battery.runtime": 1260,
+    ///     "ups.status": "OB DISCHRG",
+    ///     "input.voltage": "0.0"
+    /// })).unwrap();
+    /// assert_eq!(87.0, status.charge_percent);
+    /// assert_eq!(1260, status.runtime_seconds);
+    /// assert!(status.on_battery);
+    /// assert!(!status.charging);
+    /// ```
+    /// # Param
+    /// * status : The UPS status, as stored in the `last_state` of the battery.
+    /// # Return
+    /// The status of the UPS, or None if the status has no charge.
+    pub fn from_value(status: &Value) -> Option<UpsStatus> {
+        let charge_percent = number_field(status, "battery.charge")? as f32;
+        let ups_status = status["ups.status"].as_str().unwrap_or("");
+        Some(UpsStatus {
+            charge_percent,
+            runtime_seconds: number_field(status, "battery.runtime").unwrap_or(0.0) as u32,
+            on_battery: ups_status.split_whitespace().any(|flag| flag == "OB"),
+            charging: ups_status.split_whitespace().any(|flag| flag == "CHRG"),
+            input_voltage: number_field(status, "input.voltage").unwrap_or(0.0) as f32,
+        })
+    }
+}
+
+/// Gets the EnergyStorage state google wants for a UPS from its status page.
+/// # Param
+/// * status : The UPS status, as stored in the `last_state` of the battery.
+/// # Return
+/// The `descriptiveCapacityRemaining`, `capacityRemaining`, `isCharging` and `isPluggedIn` states, or an empty object
+/// if the status has no charge.
+pub fn energy_storage_state(status: &Value) -> Value {
+    let status = match UpsStatus::from_value(status) {
+        Some(status) => status,
+        None => return json!({}),
+    };
+    let charge = status.charge_percent;
+    let descriptive = if charge >= 100.0 {
+        "FULL"
+    } else if charge >= 70.0 {
+        "HIGH"
+    } else if charge >= 40.0 {
+        "MEDIUM"
+    } else if charge >= 10.0 {
+        "LOW"
+    } else {
+        "CRITICALLY_LOW"
+    };
+    json!({
+        "descriptiveCapacityRemaining": descriptive,
+        "capacityRemaining": [{
+            "rawValue": charge.round() as u64,
+            "unit": "PERCENTAGE"
+        }],
+        "isCharging": status.charging,
+        "isPluggedIn": !status.on_battery
+    })
+}
+
+/// Reads a number out of the UPS status, which may be given as a number or as a string (`"100"`).
+fn number_field(status: &Value, field: &str) -> Option<f64> {
+    match &status[field] {
+        Value::Number(number) => number.as_f64(),
+        Value::String(text) => text.trim().parse().ok(),
+        _ => None,
+    }
+}
@@ -0,0 +1,507 @@
+use std::ops::RangeInclusive;
+
+use serde_json::Value;
+
+use crate::device::{Device, DeviceTrait, DeviceType};
+#[cfg(feature = "tv")]
+use crate::tv;
+
+/// Everything google needs to know about a kind of device: its type, its traits and its attributes. Each device type
+/// has one implementation, and `for_kind` maps a `DeviceType` to it, so adding a device type only touches this
+/// module (and the `FromStr` of `DeviceType`).
+pub trait DeviceCapabilities {
+    /// Gets the google home device type, ie `action.devices.types.LIGHT`
+    fn google_type(&self) -> &'static str;
+
+    /// Gets the google home traits of the given device, ie `action.devices.traits.OnOff`
+    fn google_traits(&self, device: &Device) -> Vec<&'static str>;
+
+    /// Gets the google home attributes of the given device.
+    fn attributes(&self, device: &Device) -> Value;
+
+    /// Gets what the given device can do, without anything specific to google. Kinds that can't be controlled keep
+    /// the default, which supports nothing.
+    fn model(&self, _device: &Device) -> DeviceCapabilitiesModel {
+        DeviceCapabilitiesModel::default()
+    }
+}
+
+/// What a device can do, independent of the smart home platform it is exposed to (google home, HomeKit...).
+/// # Example
+///```
+/// use aa_models::device::{Device, DeviceType};
+/// use serde_json::json;
+///
+/// let mut device = Device::default();
+/// device.kind = DeviceType::LIGHT;
+/// device.last_state = json!({"on": true, "brightness": 40});
+/// let model = device.capabilities();
+/// assert!(model.on_off);
+/// assert_eq!(Some(0..=100), model.brightness);
+/// assert!(!model.open_close);
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct DeviceCapabilitiesModel {
+    /// Whether or not the device can be turned on and off.
+    pub on_off: bool,
+    /// The brightness the device can be set to, if it can be dimmed.
+    pub brightness: Option<RangeInclusive<u8>>,
+    /// The volume the device can be set to, if it has one.
+    pub volume: Option<RangeInclusive<u8>>,
+    /// Whether or not the device can be opened and closed.
+    pub open_close: bool,
+    /// Whether or not the device can change color.
+    pub color: bool,
+    /// Whether or not the device can be rebooted.
+    pub reboot: bool,
+}
+
+impl DeviceCapabilitiesModel {
+    /// Gets the model of a device that can only be turned on and off.
+    fn on_off() -> DeviceCapabilitiesModel {
+        DeviceCapabilitiesModel {
+            on_off: true,
+            ..DeviceCapabilitiesModel::default()
+        }
+    }
+}
+
+/// Lights, which can set a color when they report one in their state.
+pub struct Light;
+/// Plain on/off switches.
+pub struct Switch;
+/// On/off outlets.
+pub struct Outlet;
+/// Garage doors.
+pub struct Garage;
+/// The zones of a SQLSprinkler host.
+pub struct Sprinkler;
+/// Routers.
+pub struct Router;
+/// SQLSprinkler hosts, which are switched on/off as a whole.
+pub struct SqlSprinklerHost;
+/// LG TV's.
+pub struct Tv;
+/// UPS batteries.
+pub struct Battery;
+/// Read-only sensors, which report readings but take no commands.
+pub struct Sensor;
+
+/// Gets the capabilities of the given device type.
+/// # Example
+///```
+/// use aa_models::capabilities::{for_kind, DeviceCapabilities};
+/// use aa_models::device::DeviceType;
+///
+/// assert_eq!("action.devices.types.OUTLET", for_kind(&DeviceType::OUTLET).google_type());
+/// ```
+pub fn for_kind(kind: &DeviceType) -> &'static dyn DeviceCapabilities {
+    match kind {
+        DeviceType::LIGHT => &Light,
+        DeviceType::SWITCH => &Switch,
+        DeviceType::OUTLET => &Outlet,
+        DeviceType::GARAGE => &Garage,
+        DeviceType::SPRINKLER => &Sprinkler,
+        DeviceType::ROUTER => &Router,
+        DeviceType::SqlSprinklerHost => &SqlSprinklerHost,
+        DeviceType::TV => &Tv,
+        DeviceType::BATTERY => &Battery,
+        DeviceType::SENSOR => &Sensor,
+    }
+}
+
+impl DeviceCapabilities for Light {
+    fn google_type(&self) -> &'static str {
+        "action.devices.types.LIGHT"
+    }
+
+    fn google_traits(&self, device: &Device) -> Vec<&'static str> {
+        let mut traits = Device::on_off();
+        if device.brightness_percent().is_some() {
+            traits.append(&mut Device::brightness());
+        }
+        if device.has_color() {
+            traits.append(&mut Device::color_setting());
+        }
+        traits
+    }
+
+    fn attributes(&self, device: &Device) -> Value {
+        let mut attributes = on_off_attribute();
+        if device.has_color() {
+            attributes["colorModel"] = Value::from("rgb");
+        }
+        attributes
+    }
+
+    fn model(&self, device: &Device) -> DeviceCapabilitiesModel {
+        DeviceCapabilitiesModel {
+            brightness: if device.last_state["brightness"].is_number() { Some(0..=100) } else { None },
+            color: device.has_color(),
+            ..DeviceCapabilitiesModel::on_off()
+        }
+    }
+}
+
+impl DeviceCapabilities for Switch {
+    fn google_type(&self) -> &'static str {
+        "action.devices.types.SWITCH"
+    }
+
+    fn google_traits(&self, _device: &Device) -> Vec<&'static str> {
+        Device::on_off()
+    }
+
+    fn attributes(&self, _device: &Device) -> Value {
+        on_off_attribute()
+    }
+
+    fn model(&self, _device: &Device) -> DeviceCapabilitiesModel {
+        DeviceCapabilitiesModel::on_off()
+    }
+}
+
+impl DeviceCapabilities for Outlet {
+    fn google_type(&self) -> &'static str {
+        "action.devices.types.OUTLET"
+    }
+
+    fn google_traits(&self, _device: &Device) -> Vec<&'static str> {
+        Device::on_off()
+    }
+
+    fn attributes(&self, _device: &Device) -> Value {
+        on_off_attribute()
+    }
+
+    fn model(&self, _device: &Device) -> DeviceCapabilitiesModel {
+        DeviceCapabilitiesModel::on_off()
+    }
+}
+
+impl DeviceCapabilities for Garage {
+    fn google_type(&self) -> &'static str {
+        "action.devices.types.GARAGE"
+    }
+
+    fn google_traits(&self, _device: &Device) -> Vec<&'static str> {
+        Device::open_close()
+    }
+
+    fn attributes(&self, _device: &Device) -> Value {
+        garage_attribute()
+    }
+
+    fn model(&self, _device: &Device) -> DeviceCapabilitiesModel {
+        DeviceCapabilitiesModel {
+            open_close: true,
+            ..DeviceCapabilitiesModel::default()
+        }
+    }
+}
+
+impl DeviceCapabilities for Sprinkler {
+    fn google_type(&self) -> &'static str {
+        "action.devices.types.SPRINKLER"
+    }
+
+    fn google_traits(&self, device: &Device) -> Vec<&'static str> {
+        let mut traits = Device::on_off();
+        if device.start_stop {
+            traits.append(&mut Device::start_stop());
+        }
+        traits.append(&mut Device::status_report());
+        traits
+    }
+
+    fn attributes(&self, device: &Device) -> Value {
+        let mut attributes = on_off_attribute();
+        if device.start_stop {
+            // Zones run until they are stopped or their timer runs out, the host can't pause them.
+            attributes["pausable"] = Value::from(false);
+        }
+        attributes
+    }
+
+    fn model(&self, _device: &Device) -> DeviceCapabilitiesModel {
+        DeviceCapabilitiesModel::on_off()
+    }
+}
+
+impl DeviceCapabilities for Router {
+    fn google_type(&self) -> &'static str {
+        "action.devices.types.ROUTER"
+    }
+
+    fn google_traits(&self, _device: &Device) -> Vec<&'static str> {
+        <Device as DeviceTrait>::reboot()
+    }
+
+    fn attributes(&self, _device: &Device) -> Value {
+        on_off_attribute()
+    }
+
+    fn model(&self, _device: &Device) -> DeviceCapabilitiesModel {
+        DeviceCapabilitiesModel {
+            reboot: true,
+            ..DeviceCapabilitiesModel::default()
+        }
+    }
+}
+
+impl DeviceCapabilities for SqlSprinklerHost {
+    fn google_type(&self) -> &'static str {
+        "action.devices.types.SWITCH"
+    }
+
+    fn google_traits(&self, _device: &Device) -> Vec<&'static str> {
+        Device::on_off()
+    }
+
+    fn attributes(&self, _device: &Device) -> Value {
+        on_off_attribute()
+    }
+
+    fn model(&self, _device: &Device) -> DeviceCapabilitiesModel {
+        DeviceCapabilitiesModel::on_off()
+    }
+}
+
+impl DeviceCapabilities for Tv {
+    fn google_type(&self) -> &'static str {
+        "action.devices.types.TV"
+    }
+
+    fn google_traits(&self, device: &Device) -> Vec<&'static str> {
+        let mut traits = Device::on_off();
+        traits.append(&mut Device::volume());
+        if tv_capabilities(device).1 {
+            traits.append(&mut Device::input_selector());
+        }
+        traits
+    }
+
+    fn attributes(&self, device: &Device) -> Value {
+        let (volume_max, input_selector) = tv_capabilities(device);
+        tv_attribute(volume_max, input_selector)
+    }
+
+    fn model(&self, device: &Device) -> DeviceCapabilitiesModel {
+        // The state is checked first, so asking for the model doesn't go to the TV when it already told us.
+        let volume_max = match device.last_state["volumeMax"].as_u64() {
+            Some(volume_max) => volume_max.min(u8::MAX as u64) as u8,
+            None => tv_capabilities(device).0,
+        };
+        DeviceCapabilitiesModel {
+            volume: Some(0..=volume_max),
+            ..DeviceCapabilitiesModel::on_off()
+        }
+    }
+}
+
+impl DeviceCapabilities for Battery {
+    fn google_type(&self) -> &'static str {
+        "action.devices.types.SENSOR"
+    }
+
+    fn google_traits(&self, _device: &Device) -> Vec<&'static str> {
+        Device::energy_storage()
+    }
+
+    fn attributes(&self, _device: &Device) -> Value {
+        battery_attribute()
+    }
+}
+
+impl DeviceCapabilities for Sensor {
+    fn google_type(&self) -> &'static str {
+        "action.devices.types.SENSOR"
+    }
+
+    fn google_traits(&self, device: &Device) -> Vec<&'static str> {
+        let state = &device.last_state;
+        let mut traits = vec![];
+        if state[TEMPERATURE].is_number() {
+            traits.append(&mut Device::temperature_control());
+        }
+        if state[HUMIDITY].is_number() {
+            traits.append(&mut Device::humidity_setting());
+        }
+        // Sensors that report nothing yet are still sensors.
+        if traits.is_empty() || !sensor_state_data(state).is_empty() {
+            traits.append(&mut Device::sensor_state());
+        }
+        traits
+    }
+
+    fn attributes(&self, device: &Device) -> Value {
+        let state = &device.last_state;
+        let mut attributes = sensor_attribute(state);
+        if state[TEMPERATURE].is_number() {
+            attributes["queryOnlyTemperatureControl"] = Value::from(true);
+            attributes["temperatureUnitForUX"] = Value::from(device.temperature_unit.as_str());
+            attributes["temperatureRange"] = serde_json::json!({
+                "minThresholdCelsius": -40,
+                "maxThresholdCelsius": 85
+            });
+        }
+        if state[HUMIDITY].is_number() {
+            attributes["queryOnlyHumiditySetting"] = Value::from(true);
+        }
+        attributes
+    }
+}
+
+/// The reading in the state of a sensor that holds its temperature, reported through the TemperatureControl trait.
+pub const TEMPERATURE: &str = "temperature";
+
+/// The reading in the state of a sensor that holds its relative humidity, reported through the HumiditySetting trait.
+pub const HUMIDITY: &str = "humidity";
+
+/// Gets the readings of the given sensor state that are reported through the SensorState trait, which are all of them
+/// except the temperature and the humidity.
+fn sensor_readings(state: &Value) -> Vec<(&String, &Value)> {
+    match state.as_object() {
+        Some(readings) => readings
+            .iter()
+            .filter(|(name, _)| name.as_str() != TEMPERATURE && name.as_str() != HUMIDITY)
+            .collect(),
+        None => vec![],
+    }
+}
+
+/// Gets the unit google expects the raw value of the given sensor reading in, for the readings that have one.
+fn sensor_unit(name: &str) -> Option<&'static str> {
+    match name {
+        "CarbonDioxideLevel" | "CarbonMonoxideLevel" | "VolatileOrganicCompounds" => Some("PARTS_PER_MILLION"),
+        "PM2.5" | "PM10" => Some("MICROGRAMS_PER_CUBIC_METER"),
+        "FilterLifeTime" | "PreFilterLifeTime" | "HEPAFilterLifeTime" | "Max2FilterLifeTime" => Some("PERCENTAGE"),
+        "AirQuality" => Some("AQI"),
+        _ => None,
+    }
+}
+
+/// Gets the attributes for sensors, supporting every reading the sensor reported in its state. Numeric readings are
+/// reported as raw values, text readings as descriptive states.
+/// # Return
+/// The attributes needed for sensors
+fn sensor_attribute(state: &Value) -> Value {
+    let supported: Vec<Value> = sensor_readings(state)
+        .into_iter()
+        .filter_map(|(name, reading)| match reading {
+            Value::Number(..) => {
+                let mut supported = serde_json::json!({ "name": name, "numericCapabilities": {} });
+                if let Some(unit) = sensor_unit(name) {
+                    supported["numericCapabilities"]["rawValueUnit"] = Value::from(unit);
+                }
+                Some(supported)
+            }
+            Value::String(current) => Some(serde_json::json!({
+                "name": name,
+                "descriptiveCapabilities": { "availableStates": [current] }
+            })),
+            _ => None,
+        })
+        .collect();
+    serde_json::json!({ "sensorStatesSupported": supported })
+}
+
+/// Gets the readings of a sensor, as google wants them for the `currentSensorStateData` of the SensorState trait. The
+/// temperature and humidity have traits of their own, so they are left out.
+/// # Example
+///```
+/// use aa_models::capabilities::sensor_state_data;
+/// use serde_json::json;
+///
+/// let data = sensor_state_data(&json!({"CarbonDioxideLevel": 412, "AirQuality": "healthy", "temperature": 21.5}));
+/// assert_eq!(2, data.len());
+/// assert!(data.contains(&json!({"name": "CarbonDioxideLevel", "rawValue": 412})));
+/// assert!(data.contains(&json!({"name": "AirQuality", "currentSensorState": "healthy"})));
+/// ```
+pub fn sensor_state_data(state: &Value) -> Vec<Value> {
+    sensor_readings(state)
+        .into_iter()
+        .filter_map(|(name, reading)| match reading {
+            Value::Number(..) => Some(serde_json::json!({ "name": name, "rawValue": reading })),
+            Value::String(..) => Some(serde_json::json!({ "name": name, "currentSensorState": reading })),
+            _ => None,
+        })
+        .collect()
+}
+
+/// Gets attributes for garage doors
+/// # Return
+/// The attributes needed for garage doors.
+fn garage_attribute() -> Value {
+    serde_json::json!({
+        "discreteOnlyOpenClose": true,
+        "openDirection": ["UP","DOWN"]
+    })
+}
+
+/// Gets the attributes for on/off devices (switches, outlets, some lights)
+/// # Return
+/// The attributes needed for on/off devices
+fn on_off_attribute() -> Value {
+    serde_json::json!({
+        "commandOnlyOnOff": false,
+        "queryOnlyOnOff": false
+    })
+}
+
+fn battery_attribute() -> Value {
+    serde_json::json!({
+        "queryOnlyEnergyStorage": true,
+        "isRechargeable": true
+    })
+}
+
+/// Gets what the TV can do, as its max volume and whether or not it can switch inputs. The TV itself is never asked,
+/// see `tv::capabilities`.
+#[cfg(feature = "tv")]
+fn tv_capabilities(device: &Device) -> (u8, bool) {
+    let capabilities = tv::capabilities(device);
+    (capabilities.volume_max, capabilities.input_selector)
+}
+
+/// Gets what the TV can do, as its max volume and whether or not it can switch inputs. Without the `tv` feature
+/// inputs can't be switched.
+#[cfg(not(feature = "tv"))]
+fn tv_capabilities(_device: &Device) -> (u8, bool) {
+    (100, false)
+}
+
+/// Gets the inputs the TV can be switched to.
+#[cfg(feature = "tv")]
+fn tv_inputs() -> Value {
+    tv::available_inputs()
+}
+
+/// Gets the inputs the TV can be switched to. Without the `tv` feature there are none.
+#[cfg(not(feature = "tv"))]
+fn tv_inputs() -> Value {
+    Value::Array(vec![])
+}
+
+/// Gets all the attributes needed for TV's
+/// # Param
+/// * `volume_max` The highest volume the TV can be set to.
+/// * `input_selector` Whether or not the TV can switch inputs.
+/// # Return
+/// The attributes needed for TV's
+fn tv_attribute(volume_max: u8, input_selector: bool) -> Value {
+    let mut attributes = serde_json::json!({
+        "commandOnlyOnOff": false,
+        "queryOnlyOnOff": false,
+        "volumeMaxLevel": volume_max,
+        "volumeCanMuteAndUnmute": true,
+        "levelStepSize": 1,
+        "commandOnlyVolume": false,
+        "volumeDefaultPercentage": 10
+    });
+    if input_selector {
+        attributes["availableInputs"] = tv_inputs();
+        attributes["orderedInputs"] = Value::from(true);
+    }
+    attributes
+}
@@ -0,0 +1,249 @@
+use std::fmt;
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::device::GoogleTrait;
+use crate::error::DeviceError;
+
+/// Turns a device on or off, with the `on` param.
+pub const ON_OFF: &str = "action.devices.commands.OnOff";
+
+/// Opens or closes a door, with the `openPercent` param.
+pub const OPEN_CLOSE: &str = "action.devices.commands.OpenClose";
+
+/// Reboots a device, without params.
+pub const REBOOT: &str = "action.devices.commands.Reboot";
+
+/// Sets the brightness of a light, with the `brightness` param.
+pub const BRIGHTNESS_ABSOLUTE: &str = "action.devices.commands.BrightnessAbsolute";
+
+/// Sets the color of a light, with the `color` param.
+pub const COLOR_ABSOLUTE: &str = "action.devices.commands.ColorAbsolute";
+
+/// Sets the volume of a TV, with the `volumeLevel` param.
+pub const SET_VOLUME: &str = "action.devices.commands.setVolume";
+
+/// Changes the volume of a TV by some steps, with the `relativeSteps` param.
+pub const VOLUME_RELATIVE: &str = "action.devices.commands.volumeRelative";
+
+/// Mutes or unmutes a TV, with the `mute` param.
+pub const MUTE: &str = "action.devices.commands.mute";
+
+/// Switches the input of a TV, with the `newInput` param.
+pub const SET_INPUT: &str = "action.devices.commands.SetInput";
+
+/// Starts or stops a sprinkler zone, with the `start` param.
+pub const START_STOP: &str = "action.devices.commands.StartStop";
+
+/// The params of a command in a google EXECUTE intent. Please see:
+/// https://developers.google.com/assistant/smarthome/reference/intent/execute
+/// for the params of every command. Each command only sets the params it uses, so every accessor gives None when the
+/// param wasn't sent.
+/// # Example
+/// ```
+/// use aa_models::command::CommandParams;
+/// use serde_json::json;
+/// let params = CommandParams::from_value(&json!({"on": true})).unwrap();
+/// assert_eq!(Some(true), params.on());
+/// assert_eq!(None, params.volume_level());
+/// // Params of the wrong type are refused instead of being ignored.
+/// assert!(CommandParams::from_value(&json!({"volumeLevel": "loud"})).is_err());
+/// ```
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct CommandParams {
+    on: Option<bool>,
+    brightness: Option<u8>,
+    volume_level: Option<u8>,
+    relative_steps: Option<i32>,
+    mute: Option<bool>,
+    open_percent: Option<u8>,
+    new_input: Option<String>,
+    color: Option<ColorParams>,
+    start: Option<bool>,
+}
+
+/// The `color` param of the ColorAbsolute command.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+struct ColorParams {
+    #[serde(rename = "spectrumRGB")]
+    spectrum_rgb: Option<u64>,
+}
+
+impl CommandParams {
+    /// Reads the params of a command, as google sent them.
+    /// # Return
+    /// The params, or `DeviceError::Deserialize` if a param doesn't have the type google documents for it.
+    pub fn from_value(params: &Value) -> Result<CommandParams, DeviceError> {
+        serde_json::from_value(params.clone()).map_err(|e| DeviceError::Deserialize(e.to_string()))
+    }
+
+    /// Whether the device should be turned on or off.
+    pub fn on(&self) -> Option<bool> {
+        self.on
+    }
+
+    /// The brightness to set the light to, from 0 to 100.
+    pub fn brightness(&self) -> Option<u8> {
+        self.brightness
+    }
+
+    /// The volume to set the TV to.
+    pub fn volume_level(&self) -> Option<u8> {
+        self.volume_level
+    }
+
+    /// How many steps to change the volume by, negative to turn it down.
+    pub fn relative_steps(&self) -> Option<i32> {
+        self.relative_steps
+    }
+
+    /// Whether the TV should be muted or unmuted.
+    pub fn mute(&self) -> Option<bool> {
+        self.mute
+    }
+
+    /// How far to open the door, from 0 (closed) to 100 (fully open).
+    pub fn open_percent(&self) -> Option<u8> {
+        self.open_percent
+    }
+
+    /// The key of the input to switch the TV to, ie `HDMI_2`.
+    pub fn new_input(&self) -> Option<&str> {
+        self.new_input.as_deref()
+    }
+
+    /// Whether the zone should be started or stopped.
+    pub fn start(&self) -> Option<bool> {
+        self.start
+    }
+
+    /// The color to set the light to, as a single RGB integer.
+    pub fn spectrum_rgb(&self) -> Option<u64> {
+        self.color.as_ref().and_then(|color| color.spectrum_rgb)
+    }
+}
+
+/// Gets the trait a device needs for the given command.
+/// # Example
+/// ```
+/// use aa_models::command::{required_trait, ON_OFF};
+/// use aa_models::device::GoogleTrait;
+/// assert_eq!(Some(GoogleTrait::OnOff), required_trait(ON_OFF));
+/// assert_eq!(None, required_trait("action.devices.commands.Dock"));
+/// ```
+/// # Return
+/// The trait, or None if the command isn't one this crate knows how to run.
+pub fn required_trait(command: &str) -> Option<GoogleTrait> {
+    match command {
+        ON_OFF => Some(GoogleTrait::OnOff),
+        OPEN_CLOSE => Some(GoogleTrait::OpenClose),
+        REBOOT => Some(GoogleTrait::Reboot),
+        BRIGHTNESS_ABSOLUTE => Some(GoogleTrait::Brightness),
+        COLOR_ABSOLUTE => Some(GoogleTrait::ColorSetting),
+        SET_VOLUME | VOLUME_RELATIVE | MUTE => Some(GoogleTrait::Volume),
+        SET_INPUT => Some(GoogleTrait::InputSelector),
+        START_STOP => Some(GoogleTrait::StartStop),
+        _ => None,
+    }
+}
+
+/// The PIN that has to be given before a command is run on a device. It is stored with the device, but never printed,
+/// so it doesn't end up in logs.
+/// # Example
+/// ```
+/// use aa_models::command::Pin;
+/// let pin = Pin::new("1234");
+/// assert!(pin.matches("1234"));
+/// assert!(!pin.matches("4321"));
+/// assert_eq!("Pin(***)", format!("{:?}", pin));
+/// ```
+#[derive(Serialize, Deserialize, Clone, PartialEq, Eq)]
+#[serde(transparent)]
+pub struct Pin(String);
+
+impl Pin {
+    /// Creates a PIN.
+    pub fn new(pin: &str) -> Pin {
+        Pin(pin.to_string())
+    }
+
+    /// Checks whether or not the given PIN is this one. Every character is compared, so how long this takes doesn't
+    /// tell how much of the PIN was right.
+    pub fn matches(&self, pin: &str) -> bool {
+        let expected = self.0.as_bytes();
+        let given = pin.as_bytes();
+        let differences = expected
+            .iter()
+            .zip(given.iter())
+            .fold(0u8, |differences, (a, b)| differences | (a ^ b));
+        expected.len() == given.len() && differences == 0
+    }
+}
+
+impl fmt::Debug for Pin {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Pin(***)")
+    }
+}
+
+/// The secondary verification google sent along with a command, as the `challenge` of the command.
+/// # Example
+/// ```
+/// use aa_models::command::Challenge;
+/// use serde_json::json;
+/// let challenge: Challenge = serde_json::from_value(json!({"pin": "1234"})).unwrap();
+/// assert_eq!(Some("1234"), challenge.pin());
+/// assert!(!challenge.ack());
+/// ```
+#[derive(Serialize, Deserialize, Clone, Default, PartialEq, Eq)]
+pub struct Challenge {
+    #[serde(default)]
+    ack: bool,
+    pin: Option<String>,
+}
+
+impl Challenge {
+    /// Whether or not the user acknowledged the command.
+    pub fn ack(&self) -> bool {
+        self.ack
+    }
+
+    /// The PIN the user gave, if any.
+    pub fn pin(&self) -> Option<&str> {
+        self.pin.as_deref()
+    }
+}
+
+impl fmt::Debug for Challenge {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Challenge")
+            .field("ack", &self.ack)
+            .field("pin", &self.pin.as_ref().map(|_| "***"))
+            .finish()
+    }
+}
+
+/// The secondary verification a command needs before it is run. Please see:
+/// https://developers.google.com/assistant/smarthome/develop/secondary-user-verification
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChallengeType {
+    /// The user has to acknowledge the command.
+    AckNeeded,
+    /// The user has to give the PIN of the device.
+    PinNeeded,
+    /// The user gave the wrong PIN, and has to give it again.
+    ChallengeFailedPinNeeded,
+}
+
+impl ChallengeType {
+    /// Gets the name google uses for this challenge, ie `pinNeeded`.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ChallengeType::AckNeeded => "ackNeeded",
+            ChallengeType::PinNeeded => "pinNeeded",
+            ChallengeType::ChallengeFailedPinNeeded => "challengeFailedPinNeeded",
+        }
+    }
+}
@@ -0,0 +1,103 @@
+use std::env;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::RwLock;
+
+use aa_consts::{get_firebase_devices, get_firebase_users};
+use firebase::Firebase;
+
+use crate::error::DeviceError;
+
+/// The firebase databases used instead of the ones from `aa_consts`, if any.
+static FIREBASE_OVERRIDE: RwLock<Option<FirebaseConfig>> = RwLock::new(None);
+
+/// Whether or not writes are only logged, see `set_dry_run`.
+static DRY_RUN: AtomicBool = AtomicBool::new(false);
+
+/// Where the users and devices are stored, for running against a database other than production (ie staging, or a
+/// database made for integration tests).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FirebaseConfig {
+    /// The url of the database holding the users.
+    pub users_url: String,
+    /// The url of the database holding the devices.
+    pub devices_url: String,
+    /// The key used to authenticate against both databases.
+    pub auth: String,
+}
+
+impl FirebaseConfig {
+    /// Reads the config from the `AA_FIREBASE_USERS_URL`, `AA_FIREBASE_DEVICES_URL` and `AA_FIREBASE_AUTH`
+    /// environment variables.
+    /// # Return
+    /// The config, or None if any of the variables is not set.
+    pub fn from_env() -> Option<FirebaseConfig> {
+        Some(FirebaseConfig {
+            users_url: env::var("AA_FIREBASE_USERS_URL").ok()?,
+            devices_url: env::var("AA_FIREBASE_DEVICES_URL").ok()?,
+            auth: env::var("AA_FIREBASE_AUTH").ok()?,
+        })
+    }
+}
+
+/// Makes every device function use the given databases, or go back to the ones from `aa_consts` when given None.
+/// This applies to every thread.
+/// # Example
+///```
+/// use aa_models::config::{set_firebase_config, FirebaseConfig};
+///
+/// if let Some(config) = FirebaseConfig::from_env() {
+///     set_firebase_config(Some(config)).unwrap();
+/// }
+/// // ... run against the configured databases ...
+/// set_firebase_config(None).unwrap();
+/// ```
+/// # Return
+/// Ok, or `DeviceError::Network` if one of the urls is not a valid firebase url.
+pub fn set_firebase_config(config: Option<FirebaseConfig>) -> Result<(), DeviceError> {
+    if let Some(config) = &config {
+        for url in &[&config.users_url, &config.devices_url] {
+            Firebase::authed(url, &config.auth)
+                .map_err(|e| DeviceError::Network(format!("{} is not a valid firebase url: {:?}", url, e)))?;
+        }
+    }
+    *FIREBASE_OVERRIDE.write().unwrap() = config;
+    Ok(())
+}
+
+/// Gets the database holding the users.
+pub(crate) fn firebase_users() -> Firebase {
+    match &*FIREBASE_OVERRIDE.read().unwrap() {
+        Some(config) => Firebase::authed(&config.users_url, &config.auth).unwrap(),
+        None => get_firebase_users(),
+    }
+}
+
+/// Gets the database holding the devices.
+pub(crate) fn firebase_devices() -> Firebase {
+    match &*FIREBASE_OVERRIDE.read().unwrap() {
+        Some(config) => Firebase::authed(&config.devices_url, &config.auth).unwrap(),
+        None => get_firebase_devices(),
+    }
+}
+
+/// Turns dry-run mode on or off for every thread. In dry-run mode nothing is changed: SQLSprinkler zones and systems,
+/// Arduino relays and the TV aren't set, routers aren't rebooted, and neither devices nor device lists are written to
+/// firebase. The request is still built, then logged instead of sent, and reported as a success. Reads still go out,
+/// so this is meant for testing automation rules against real devices without flipping anything.
+/// # Example
+///```
+/// use aa_models::config::{is_dry_run, set_dry_run};
+///
+/// set_dry_run(true);
+/// assert!(is_dry_run());
+/// // ... run the rules ...
+/// set_dry_run(false);
+/// ```
+pub fn set_dry_run(dry_run: bool) {
+    DRY_RUN.store(dry_run, Ordering::SeqCst);
+}
+
+/// Checks whether or not dry-run mode is on, see `set_dry_run`.
+pub fn is_dry_run() -> bool {
+    DRY_RUN.load(Ordering::SeqCst)
+}
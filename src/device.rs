@@ -1,20 +1,73 @@
+use std::collections::{HashMap, HashSet};
 use std::fmt;
 use std::process::Command;
 use std::str::FromStr;
+use std::thread;
+use std::time::{Duration, Instant};
 
-use aa_consts::*;
+use chrono::{DateTime, Utc};
 use isahc::http::StatusCode;
-use log::debug;
+use log::{debug, error, info, warn};
 use serde::{Deserialize, Serialize};
 use serde_json::{json, Value};
 
-use crate::{battery, tv};
+#[cfg(feature = "battery")]
+use crate::battery;
+use crate::command::{self, Challenge, ChallengeType, CommandParams, Pin};
+use crate::{capabilities, router};
+#[cfg(feature = "tv")]
+use crate::tv;
+use crate::config::{firebase_devices, firebase_users, is_dry_run};
+use crate::error::{network, DeviceError};
+use crate::transport::{AuthToken, HttpTransport, IsahcTransport, RequestOptions};
+#[cfg(feature = "sqlsprinkler")]
 use crate::sqlsprinkler::*;
 
+/// Checks to see if the given guid is a SQLSprinkler zone. Without the `sqlsprinkler` feature there are no zones.
+#[cfg(feature = "sqlsprinkler")]
+fn is_zone_guid(guid: &String) -> bool {
+    check_if_zone(guid)
+}
+
+/// Checks to see if the given guid is a SQLSprinkler zone. Without the `sqlsprinkler` feature there are no zones.
+#[cfg(not(feature = "sqlsprinkler"))]
+fn is_zone_guid(_guid: &String) -> bool {
+    false
+}
+
+/// Gets the guid of the SQLSprinkler host of the given zone guid.
+#[cfg(feature = "sqlsprinkler")]
+fn host_guid_of_zone(guid: &str) -> Option<String> {
+    zone_host_guid(guid)
+}
+
+/// Gets the guid of the SQLSprinkler host of the given zone guid. Without the `sqlsprinkler` feature there are no
+/// zones.
+#[cfg(not(feature = "sqlsprinkler"))]
+fn host_guid_of_zone(_guid: &str) -> Option<String> {
+    None
+}
+
+/// Gets the endpoint of the given relay of an Arduino.
+fn relay_endpoint(index: u8) -> String {
+    format!("relay/{}", index)
+}
+
+/// The fields of a device that are left out of `Device::to_public_json`: where the device is on the network, who it
+/// belongs to, and its secrets.
+const PRIVATE_FIELDS: [&str; 5] = ["ip", "mac", "useruuid", "pin", "auth_token"];
+
+/// How long requests to a device may take when it has no `request_timeout_secs` of its own.
+pub const DEFAULT_REQUEST_TIMEOUT: Duration = Duration::from_secs(3);
+
+/// How often a device is polled when it has neither a `poll_interval_secs` nor a reporting interval of its own.
+pub const DEFAULT_POLL_INTERVAL: Duration = Duration::from_secs(5 * 60);
+
 /// Data representing a device that can be automated/remotely controlled.
-#[derive(Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[derive(Debug, Serialize, Deserialize)]
 pub struct Device {
     /// The IP of the device (sometimes used)
+    #[serde(default)]
     pub ip: String,
 
     /// The GUID of the device
@@ -30,20 +83,127 @@ pub struct Device {
     pub last_state: Value,
 
     /// The current software version on the device.
+    #[serde(default)]
     pub sw_version: String,
 
     /// The user this device belongs to.
     pub useruuid: String,
 
     /// The name of the device
+    #[serde(default)]
     pub name: String,
 
     /// A list of nicknames for the device
+    #[serde(default)]
     pub nicknames: Vec<String>,
+
+    /// How this device reports its state, if the server has a configuration for it.
+    #[serde(default)]
+    pub reporting: Option<ReportingConfig>,
+
+    /// How long, in seconds, requests to this device may take before giving up. Defaults to 3 seconds.
+    #[serde(default)]
+    pub request_timeout_secs: Option<u64>,
+
+    /// The icon the app shows for this device, display only.
+    #[serde(default)]
+    pub icon: Option<String>,
+
+    /// The color the app shows this device's tile in, display only.
+    #[serde(default)]
+    pub color: Option<String>,
+
+    /// The model the device reported about itself, if it did.
+    #[serde(default)]
+    pub model: Option<String>,
+
+    /// When the device last answered a ping, see `refresh_online`.
+    #[serde(default)]
+    pub last_seen: Option<DateTime<Utc>>,
+
+    /// Whether or not `last_state` is only what was last stored, without asking the device (see
+    /// `get_stored_device_from_guid`). This is never stored, and two devices that only differ in it are equal.
+    #[serde(skip)]
+    pub stale: bool,
+
+    /// The unit the device reports its temperatures in, Celsius when not set.
+    #[serde(default, rename = "thermostatTemperatureUnit")]
+    pub temperature_unit: TemperatureUnit,
+
+    /// The PIN that has to be given before opening or closing this device, if it is a garage. Without one, commands
+    /// only have to be acknowledged.
+    #[serde(default)]
+    pub pin: Option<Pin>,
+
+    /// The bearer token requests to this device are sent with, for devices behind an auth proxy. It is never printed,
+    /// so it doesn't end up in logs.
+    #[serde(default)]
+    pub auth_token: Option<AuthToken>,
+
+    /// Whether or not the HTTP API of this device is served over HTTPS. Hardware that always serves HTTPS (see
+    /// `HardwareType::supports_https`) uses it either way.
+    #[serde(default)]
+    pub https: bool,
+
+    /// Whether or not to accept the self-signed certificate of this device. This only applies to devices on the LAN (a
+    /// private or loopback IP), certificates of anything else are always checked.
+    #[serde(default)]
+    pub accept_self_signed_cert: bool,
+
+    /// The MAC address of the device, if known. This is what tells TV's apart when they are found again on the LAN
+    /// (see `tv::find_tv_ip`).
+    #[serde(default)]
+    pub mac: Option<String>,
+
+    /// How often, in seconds, this device should be polled for its state. Defaults to the reporting interval the server
+    /// configured, see `Device::poll_interval`.
+    #[serde(default)]
+    pub poll_interval_secs: Option<u32>,
+
+    /// The scale the device reports its brightness on, a percent when not set. Google is always sent a percent, see
+    /// `brightness_percent`.
+    #[serde(default)]
+    pub brightness_scale: BrightnessScale,
+
+    /// The relay of the Arduino this device is wired to, for Arduinos that drive more than one relay. Devices without
+    /// one are controlled through their own endpoint, see `relay_endpoint`.
+    #[serde(default)]
+    pub relay_index: Option<u8>,
+
+    /// Whether or not this sprinkler zone is also advertised to google with the StartStop trait, so it can be started
+    /// and stopped ("start the front lawn") on top of being turned on and off.
+    #[serde(default)]
+    pub start_stop: bool,
 }
 
-/// Represents hardware types in google home
+/// The reporting preferences of a device, stored server-side at `/devices/{guid}/reporting`.
+#[derive(Debug, PartialEq, Eq, Serialize, Deserialize, Clone)]
+pub struct ReportingConfig {
+    /// How often, in seconds, the device should report its state.
+    pub interval: u32,
+
+    /// How the device reports its state.
+    #[serde(default)]
+    pub mode: ReportingMode,
+}
+
+/// Represents the ways a device can report its state.
 #[derive(Debug, PartialEq, Eq, Serialize, Deserialize, Copy, Clone)]
+pub enum ReportingMode {
+    /// The device is polled for its state.
+    POLL,
+    /// The device pushes its state on its own.
+    PUSH,
+}
+
+impl ::std::default::Default for ReportingMode {
+    fn default() -> ReportingMode {
+        ReportingMode::POLL
+    }
+}
+
+/// Represents hardware types in google home
+#[derive(Debug, PartialEq, Eq, Hash, Serialize, Deserialize, Copy, Clone)]
 pub enum HardwareType {
     ARDUINO,
     PI,
@@ -51,70 +211,168 @@ pub enum HardwareType {
     LG,
 }
 
+impl HardwareType {
+    /// Checks whether or not this hardware has an HTTP API devices can be controlled through, ie the relays of an
+    /// Arduino.
+    /// # Example
+    /// ```
+    /// use aa_models::device::HardwareType;
+    /// assert!(HardwareType::ARDUINO.supports_http_api());
+    /// assert!(!HardwareType::LG.supports_http_api());
+    /// ```
+    pub fn supports_http_api(&self) -> bool {
+        match self {
+            HardwareType::ARDUINO => true,
+            HardwareType::PI | HardwareType::OTHER | HardwareType::LG => false,
+        }
+    }
+
+    /// Checks whether or not this hardware always serves its API over HTTPS, whatever the `https` field of the device
+    /// says. See `Device::uses_https`.
+    pub fn supports_https(&self) -> bool {
+        match self {
+            HardwareType::ARDUINO | HardwareType::PI | HardwareType::OTHER | HardwareType::LG => false,
+        }
+    }
+
+    /// Checks whether or not this hardware can be updated over the air.
+    pub fn supports_ota(&self) -> bool {
+        match self {
+            HardwareType::ARDUINO => true,
+            HardwareType::PI | HardwareType::OTHER | HardwareType::LG => false,
+        }
+    }
+
+    /// Checks whether or not this hardware can be turned on with a Wake-on-LAN packet.
+    /// # Example
+    /// ```
+    /// use aa_models::device::HardwareType;
+    /// assert!(HardwareType::LG.supports_wol());
+    /// assert!(!HardwareType::ARDUINO.supports_wol());
+    /// ```
+    pub fn supports_wol(&self) -> bool {
+        match self {
+            HardwareType::LG => true,
+            HardwareType::ARDUINO | HardwareType::PI | HardwareType::OTHER => false,
+        }
+    }
+}
+
+/// The unit a device reports its temperatures in. Google always wants Celsius.
+#[derive(Debug, PartialEq, Eq, Hash, Serialize, Deserialize, Copy, Clone)]
+pub enum TemperatureUnit {
+    C,
+    F,
+}
+
+impl ::std::default::Default for TemperatureUnit {
+    fn default() -> TemperatureUnit {
+        TemperatureUnit::C
+    }
+}
+
+impl TemperatureUnit {
+    /// Converts a temperature in this unit to Celsius, rounded to one decimal so the reading doesn't jitter.
+    /// # Example
+    /// ```
+    /// use aa_models::device::TemperatureUnit;
+    /// assert_eq!(22.8, TemperatureUnit::F.to_celsius(73.0));
+    /// assert_eq!(-40.0, TemperatureUnit::F.to_celsius(-40.0));
+    /// assert_eq!(21.5, TemperatureUnit::C.to_celsius(21.54));
+    /// ```
+    pub fn to_celsius(self, temperature: f64) -> f64 {
+        let celsius = match self {
+            TemperatureUnit::C => temperature,
+            TemperatureUnit::F => (temperature - 32.0) * 5.0 / 9.0,
+        };
+        (celsius * 10.0).round() / 10.0
+    }
+
+    /// Gets the name google uses for this unit.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            TemperatureUnit::C => "C",
+            TemperatureUnit::F => "F",
+        }
+    }
+}
+
+/// The scale a device reports its brightness on. Google always wants a percent.
+#[derive(Debug, PartialEq, Eq, Hash, Serialize, Deserialize, Copy, Clone)]
+pub enum BrightnessScale {
+    /// From 0 to 100.
+    Percent,
+    /// From 0 to 255, ie arduino LED strips.
+    Byte,
+}
+
+impl ::std::default::Default for BrightnessScale {
+    fn default() -> BrightnessScale {
+        BrightnessScale::Percent
+    }
+}
+
+impl BrightnessScale {
+    /// Gets the highest brightness on this scale.
+    pub fn max(&self) -> u8 {
+        match self {
+            BrightnessScale::Percent => 100,
+            BrightnessScale::Byte => 255,
+        }
+    }
+
+    /// Converts a brightness on this scale to a percent. Values outside of the scale are clamped to it first.
+    /// # Example
+    /// ```
+    /// use aa_models::device::BrightnessScale;
+    /// assert_eq!(50, BrightnessScale::Byte.to_percent(128.0));
+    /// assert_eq!(100, BrightnessScale::Byte.to_percent(300.0));
+    /// assert_eq!(0, BrightnessScale::Percent.to_percent(-5.0));
+    /// ```
+    pub fn to_percent(self, brightness: f64) -> u8 {
+        let max = f64::from(self.max());
+        (brightness.max(0.0).min(max) * 100.0 / max).round() as u8
+    }
+
+    /// Converts a percent (ie from a google command) to a brightness on this scale. Percents above 100 are clamped.
+    /// # Example
+    /// ```
+    /// use aa_models::device::BrightnessScale;
+    /// assert_eq!(128, BrightnessScale::Byte.from_percent(50));
+    /// assert_eq!(255, BrightnessScale::Byte.from_percent(120));
+    /// assert_eq!(50, BrightnessScale::Percent.from_percent(50));
+    /// ```
+    pub fn from_percent(self, percent: u8) -> u8 {
+        let max = f64::from(self.max());
+        (f64::from(percent.min(100)) * max / 100.0).round() as u8
+    }
+}
+
 /// Represents all the different types of devices we can have / currently implemented
-#[derive(Debug, PartialEq, Eq, Serialize, Deserialize, Copy, Clone)]
+#[derive(Debug, PartialEq, Eq, Hash, Serialize, Deserialize, Copy, Clone)]
 pub enum DeviceType {
     BATTERY,
     LIGHT,
     SWITCH,
+    OUTLET,
     GARAGE,
     SPRINKLER,
     ROUTER,
     SqlSprinklerHost,
     TV,
-}
-
-/// Gets attributes for garage doors
-/// # Return
-/// The attributes needed for garage doors.
-fn garage_attribute() -> Value {
-    serde_json::json!({
-        "discreteOnlyOpenClose": true,
-        "openDirection": ["UP","DOWN"]
-    })
-}
-
-/// Gets the attributes for on/off devices (switches, outlets, some lights)
-/// # Return
-/// The attributes needed for on/off devices
-fn on_off_attribute() -> Value {
-    serde_json::json!({
-        "commandOnlyOnOff": false,
-        "queryOnlyOnOff": false
-    })
-}
-
-fn battery_attribute() -> Value {
-    serde_json::json!({
-        "queryOnlyEnergyStorage": true,
-        "isRechargeable": true
-    })
-}
-
-/// Gets all the attributes needed for TV's
-/// # Return
-/// The attributes needed for TV's
-fn tv_attribute() -> Value {
-    serde_json::json!({
-        "commandOnlyOnOff": false,
-        "queryOnlyOnOff": false,
-        "volumeMaxLevel": 100,
-        "volumeCanMuteAndUnmute": true,
-        "levelStepSize": 1,
-        "commandOnlyVolume": false,
-        "volumeDefaultPercentage": 10
-    })
+    SENSOR,
 }
 
 impl Device {
     /// Gets the API Url of the device, with the endpoint.
     /// # Return
-    /// A formatted string we can use to send requests to.
-    fn get_api_url(&self, endpoint: String) -> String {
-        match self.hardware {
-            HardwareType::ARDUINO => format!("http://{}/{}", self.ip, endpoint),
-            _ => "".to_string(),
+    /// A formatted string we can use to send requests to, or None if the hardware of this device has no HTTP API.
+    fn get_api_url(&self, endpoint: String) -> Option<String> {
+        if !self.hardware.supports_http_api() {
+            return None;
         }
+        let scheme = if self.uses_https() { "https" } else { "http" };
+        Some(format!("{}://{}/{}", scheme, self.ip, endpoint))
     }
 
     /// Get the attributes of this device. Please see https://developers.google.com/assistant/smarthome/traits/onoff#device-attributes
@@ -134,39 +392,132 @@ impl Device {
     /// # Return
     /// The attributes for this device.
     pub fn get_attributes(&self) -> Value {
-        match self.kind {
-            DeviceType::GARAGE => garage_attribute(),
-            DeviceType::LIGHT
-            | DeviceType::SWITCH
-            | DeviceType::SPRINKLER
-            | DeviceType::ROUTER
-            | DeviceType::SqlSprinklerHost => on_off_attribute(),
-            DeviceType::TV => tv_attribute(),
-            DeviceType::BATTERY => battery_attribute(),
-        }
+        capabilities::for_kind(&self.kind).attributes(self)
     }
 
-    /// Gets a URL to use for turning on/off relays on Arduinos or zones in SQLSprinkler
+    /// Gets what this device can do, from its type and its state, independent of google's traits. See
+    /// `DeviceCapabilitiesModel`.
+    pub fn capabilities(&self) -> capabilities::DeviceCapabilitiesModel {
+        capabilities::for_kind(&self.kind).model(self)
+    }
+
+    /// Gets a URL to use for turning on/off relays on Arduinos or zones in SQLSprinkler. Relays are better set with
+    /// `arduino::ArduinoClient`, which also sends the request.
     /// # Params
-    /// * endpoint : The UUID of the device we want to control.
+    /// * endpoint : The UUID of the device we want to control. Devices with a `relay_index` use their relay instead.
     /// * param :   The state we want to set this device to.
     /// # Example
     /// Get the api url for an arduino
     /// ```
     /// use aa_models::device;
     /// let device = device::get_device_from_guid(&String::from("test_light"));
-    /// println!("{}",device.get_api_url_with_param(String::from("on"),String::from("true")));
+    /// let url = device.get_api_url_with_param(String::from("on"),String::from("true"));
+    /// println!("{:?}",url);
+    /// assert!(url.is_some());
     /// ```
     /// # Return
-    /// A formatted URL we can send a request to.
-    pub fn get_api_url_with_param(&self, endpoint: String, param: String) -> String {
+    /// A formatted URL we can send a request to, or None if this device can't be controlled over HTTP.
+    pub fn get_api_url_with_param(&self, endpoint: String, param: String) -> Option<String> {
         match self.kind {
-            DeviceType::SqlSprinklerHost => format!(
+            DeviceType::SqlSprinklerHost => Some(format!(
                 "https://api.peasenet.com/sprinkler/systems/{}/state",
                 self.guid
-            ),
-            _ => format!("{}?param={}", self.get_api_url(endpoint), param),
+            )),
+            // Sensors only report readings, they can't be controlled.
+            DeviceType::SENSOR => None,
+            _ => self
+                .get_api_url(self.relay_index.map_or(endpoint, relay_endpoint))
+                .map(|url| format!("{}?param={}", url, param)),
+        }
+    }
+
+    /// Gets the endpoint of the relay this device is wired to: `relay/{index}` for devices that share an Arduino with
+    /// other relays, the guid of the device otherwise.
+    /// # Example
+    /// ```
+    /// use aa_models::device::{Device, HardwareType};
+    /// let mut device = Device::default();
+    /// device.guid = String::from("porch");
+    /// device.hardware = HardwareType::ARDUINO;
+    /// device.ip = String::from("10.0.0.5");
+    /// assert_eq!("porch", device.relay_endpoint());
+    /// device.relay_index = Some(2);
+    /// assert_eq!("relay/2", device.relay_endpoint());
+    /// let url = device.get_api_url_with_param(String::from("porch"), String::from("true"));
+    /// assert_eq!(Some(String::from("http://10.0.0.5/relay/2?param=true")), url);
+    /// ```
+    pub fn relay_endpoint(&self) -> String {
+        self.relay_index.map_or_else(|| self.guid.clone(), relay_endpoint)
+    }
+
+    /// Checks whether or not the HTTP API of this device is served over HTTPS.
+    /// # Example
+    /// ```
+    /// use aa_models::device::{Device, HardwareType};
+    /// let mut device = Device::default();
+    /// device.hardware = HardwareType::ARDUINO;
+    /// assert!(!device.uses_https());
+    /// device.https = true;
+    /// assert!(device.uses_https());
+    /// ```
+    pub fn uses_https(&self) -> bool {
+        self.https || self.hardware.supports_https()
+    }
+
+    /// Checks whether or not the self-signed certificate of this device should be accepted, which it only is for
+    /// HTTPS devices on the LAN that opted in with `accept_self_signed_cert`.
+    /// # Example
+    /// ```
+    /// use aa_models::device::Device;
+    /// let mut device = Device::default();
+    /// device.https = true;
+    /// device.accept_self_signed_cert = true;
+    /// device.ip = String::from("192.168.1.40");
+    /// assert!(device.accepts_self_signed_cert());
+    /// device.ip = String::from("192.168.1.40:8443");
+    /// assert!(device.accepts_self_signed_cert());
+    /// device.ip = String::from("8.8.8.8");
+    /// assert!(!device.accepts_self_signed_cert());
+    /// ```
+    pub fn accepts_self_signed_cert(&self) -> bool {
+        if !self.accept_self_signed_cert || !self.uses_https() {
+            return false;
+        }
+        let host = self.ip.rsplit_once(':').map_or(self.ip.as_str(), |(host, _)| host);
+        match host.parse::<std::net::Ipv4Addr>() {
+            Ok(ip) => ip.is_private() || ip.is_loopback(),
+            Err(..) => false,
+        }
+    }
+
+    /// Gets the options requests to the HTTP API of this device have to be sent with: its auth token, and whether or
+    /// not its self-signed certificate is accepted.
+    /// # Example
+    /// ```
+    /// use aa_models::device::Device;
+    /// let device = Device::default();
+    /// let options = device.request_options();
+    /// assert!(options.auth_token.is_none());
+    /// assert!(!options.accept_self_signed_cert);
+    /// ```
+    pub fn request_options(&self) -> RequestOptions {
+        RequestOptions {
+            auth_token: self.auth_token.clone(),
+            accept_self_signed_cert: self.accepts_self_signed_cert(),
+        }
+    }
+
+    /// Sends a GET request to the given url of this device, with the options of this device. See `request_options`.
+    /// # Return
+    /// The body the device answered with, or an error if it answered with an error status or could not be reached.
+    fn api_get(&self, url: &str) -> Result<String, DeviceError> {
+        // Only the url is logged, the token is never part of it.
+        debug!("Sending a request to {}: {}", self.guid, url);
+        let response = IsahcTransport.get_with_options(url, self.request_timeout(), &self.request_options())?;
+        if !response.status.is_success() {
+            return Err(DeviceError::Http(response.status, response.body));
         }
+        Ok(response.body)
     }
 
     /// Updates the device in the backend database
@@ -181,8 +532,8 @@ impl Device {
     /// let device_state = Value::from(true);
     /// device.last_state = device_state;
     /// let result = device.database_update();
-    /// println!("Device update success: {}",result);
-    /// assert!(result);
+    /// println!("Device update result: {:?}",result);
+    /// assert!(result.is_ok());
     ///
     /// // poll the device again to make sure we have updated in the db.
     /// device = device::get_device_from_guid(&String::from("test_switch"));
@@ -190,7 +541,7 @@ impl Device {
     ///
     /// // turn off the device
     /// device.last_state = Value::from(false);
-    /// device.database_update();
+    /// device.database_update().unwrap();
     ///
     /// // Check the device yet again.
     /// device = device::get_device_from_guid(&String::from("test_switch"));
@@ -202,28 +553,122 @@ impl Device {
     /// use aa_models::device;
     /// use serde_json::json;
     /// let mut device = device::get_device_from_guid(&String::from("test_light"));
-    /// // New device state
+    /// // New device state, the brightness being on the `brightness_scale` of the device.
     /// device.last_state = json!({
     ///     "on": true,
     ///     "brightness": 23
     /// });
     /// let result = device.database_update();
-    /// println!("Device update success: {}",result);
-    /// assert!(result);
+    /// println!("Device update result: {:?}",result);
+    /// assert!(result.is_ok());
+    /// ```
+    /// # Return
+    /// Ok if firebase accepted the update, `DeviceError::Firebase` if firebase answered with anything other than OK, or
+    /// `DeviceError::Network` if firebase could not be reached.
+    pub fn database_update(&self) -> Result<(), DeviceError> {
+        debug!("Updating device : {}", self);
+        let device = firebase_devices().at(&self.guid).map_err(network)?;
+        if is_dry_run() {
+            info!("[dry-run] Updating device : {}", self);
+            return Ok(());
+        }
+        let response = device.set(serde_json::to_value(&self).unwrap()).map_err(network)?;
+        debug!("Firebase responded with: {}", response.code);
+        if response.code != StatusCode::OK {
+            return Err(DeviceError::Firebase(response.code));
+        }
+        Ok(())
+    }
+
+    /// Updates the device in the backend database, but only if something google cares about changed since the given
+    /// version of it (see `diff`), so polling a device that didn't change writes nothing.
+    /// # Example
+    /// ```
+    /// use aa_models::device;
+    /// let device = device::get_device_from_guid(&String::from("test_switch"));
+    /// let previous = device.clone();
+    /// assert_eq!(false, device.database_update_if_changed(&previous).unwrap());
+    /// ```
+    /// # Params
+    /// * `previous` The device as it was last stored.
+    /// # Return
+    /// Whether or not the device was written, or the same errors as `database_update`.
+    pub fn database_update_if_changed(&self, previous: &Device) -> Result<bool, DeviceError> {
+        if !self.state_differs(previous) {
+            debug!("{} did not change, not updating it", self.guid);
+            return Ok(false);
+        }
+        self.database_update()?;
+        Ok(true)
+    }
+
+    /// Updates only the `last_state` of this device in the backend database, leaving the rest of the stored device
+    /// alone. This should be preferred over `database_update` when only the state changed, as it won't overwrite a
+    /// rename that happened at the same time.
+    /// # Example
+    /// ```
+    /// use aa_models::device;
+    /// use serde_json::Value;
+    /// let device = device::get_device_from_guid(&String::from("test_switch"));
+    /// let result = device.update_state(Value::from(false));
+    /// println!("State update result: {:?}",result);
+    /// assert!(result.is_ok());
+    /// ```
+    /// # Params
+    /// * `new_state` The state to store for this device.
+    /// # Return
+    /// Ok if firebase accepted the update, otherwise the same errors as `database_update`.
+    pub fn update_state(&self, new_state: Value) -> Result<(), DeviceError> {
+        self.firebase_patch(json!({ "last_state": new_state }))
+    }
+
+    /// Updates only the given top level fields of this device in the backend database, so that a rename doesn't
+    /// overwrite a state change that happened at the same time.
+    /// # Example
+    /// ```
+    /// use aa_models::device;
+    /// let mut device = device::get_device_from_guid(&String::from("test_switch"));
+    /// device.name = String::from("Test Switch");
+    /// device.nicknames = vec![String::from("Test")];
+    /// assert!(device.update_fields(&["name", "nicknames"]).is_ok());
+    /// // Fields that aren't on a device are refused.
+    /// assert!(device.update_fields(&["colour"]).is_err());
     /// ```
+    /// # Params
+    /// * `fields` The names of the fields to update, as they are stored in firebase.
     /// # Return
-    /// A bool representing if the update was successful.
-    pub fn database_update(&self) -> bool {
-        debug!("Updating device : {}", serde_json::to_value(self).unwrap());
-        let success = get_firebase_devices()
-            .at(&self.guid)
-            .unwrap()
-            .set(serde_json::to_value(&self).unwrap())
-            .unwrap()
-            .code
-            == StatusCode::OK;
-        debug!("success: {}", success);
-        success
+    /// Ok if firebase accepted the update, `DeviceError::UnknownField` if a field isn't on a device, otherwise the same
+    /// errors as `database_update`.
+    pub fn update_fields(&self, fields: &[&str]) -> Result<(), DeviceError> {
+        let device = serde_json::to_value(self).unwrap();
+        let mut patch = serde_json::Map::new();
+        for field in fields {
+            match device.get(*field) {
+                Some(value) => {
+                    patch.insert(field.to_string(), value.clone());
+                }
+                None => return Err(DeviceError::UnknownField(field.to_string())),
+            }
+        }
+        self.firebase_patch(Value::Object(patch))
+    }
+
+    /// Patches the given top level fields of this device in firebase.
+    fn firebase_patch(&self, patch: Value) -> Result<(), DeviceError> {
+        // Only the names of the fields are logged, as they may be the PIN or auth token.
+        let fields: Vec<&String> = patch.as_object().map_or(vec![], |fields| fields.keys().collect());
+        debug!("Patching device {} : {:?}", self.guid, fields);
+        let device = firebase_devices().at(&self.guid).map_err(network)?;
+        if is_dry_run() {
+            info!("[dry-run] Patching device {} : {:?}", self.guid, fields);
+            return Ok(());
+        }
+        let response = device.update(patch).map_err(network)?;
+        debug!("Firebase responded with: {}", response.code);
+        if response.code != StatusCode::OK {
+            return Err(DeviceError::Firebase(response.code));
+        }
+        Ok(())
     }
 
     /// Gets the device type for use in google home
@@ -247,15 +692,7 @@ impl Device {
     /// # Return
     /// A str representing the type of device that google home recognizes.
     pub fn get_google_device_type(&self) -> &str {
-        match self.kind {
-            DeviceType::LIGHT => "action.devices.types.LIGHT",
-            DeviceType::SWITCH | DeviceType::SqlSprinklerHost => "action.devices.types.SWITCH",
-            DeviceType::GARAGE => "action.devices.types.GARAGE",
-            DeviceType::SPRINKLER => "action.devices.types.SPRINKLER",
-            DeviceType::ROUTER => "action.devices.types.ROUTER",
-            DeviceType::TV => "action.devices.types.TV",
-            DeviceType::BATTERY => "action.devices.types.SENSOR",
-        }
+        capabilities::for_kind(&self.kind).google_type()
     }
 
     /// Gets a list of traits for google home that pertains to this device
@@ -263,7 +700,10 @@ impl Device {
     /// Right now, the following device types have the following traits. By default, the trait is OnOff.
     /// * Garage → OpenClose
     /// * Router → Reboot
-    /// * TV → OnOff, Volume
+    /// * TV → OnOff, Volume, InputSelector
+    /// * Light with a brightness in its state → OnOff, Brightness
+    /// * Light with a color in its state → OnOff, ColorSetting
+    /// * Sprinkler → OnOff, StatusReport
     ///
     /// # Examples
     /// ```
@@ -275,343 +715,2256 @@ impl Device {
     /// # Return
     /// A list of traits that this device has.
     pub fn get_google_device_traits(&self) -> Vec<&str> {
-        return match self.kind {
-            DeviceType::GARAGE => {
-                let traits: Vec<&str> = Device::open_close();
-                traits
-            }
-            DeviceType::ROUTER => Device::reboot(),
-            DeviceType::TV => {
-                let mut traits: Vec<&str> = Device::on_off();
-                let mut _new_traits: Vec<&str> = Device::volume();
-                traits.append(&mut _new_traits);
-                traits
-            }
-            DeviceType::BATTERY => Device::energy_storage(),
-            _ => Device::on_off(),
-        };
+        capabilities::for_kind(&self.kind).google_traits(self)
     }
 
-    /// Gets the hardware type for google home
-    ///
-    /// # Examples
-    /// This shows getting the hardware type for an "Other" device
+    /// Checks whether or not this device has the given google trait.
+    /// # Example
     /// ```
-    /// use aa_models::device;
-    /// let device = device::get_device_from_guid(&String::from("test_switch"));
-    /// println!("{}",device.get_google_device_hardware());
-    /// assert_eq!("Other",device.get_google_device_hardware());
+    /// use aa_models::device::{Device, DeviceType, GoogleTrait};
+    /// let mut device = Device::default();
+    /// device.kind = DeviceType::GARAGE;
+    /// assert!(device.supports(GoogleTrait::OpenClose));
+    /// assert!(!device.supports(GoogleTrait::Volume));
     /// ```
-    /// This shows getting the hardware type for an Arduino device
+    pub fn supports(&self, google_trait: GoogleTrait) -> bool {
+        self.get_google_device_traits().contains(&google_trait.as_str())
+    }
+
+    /// Checks whether or not this device has the google trait with the given name, either the full name
+    /// (`action.devices.traits.Volume`) or just the trait (`Volume`).
+    /// # Example
     /// ```
-    /// use aa_models::device;
-    /// let device = device::get_device_from_guid(&String::from("test_light"));
-    /// println!("{}",device.get_google_device_hardware());
-    /// assert_eq!("Arduino",device.get_google_device_hardware());
+    /// use aa_models::device::{Device, DeviceType};
+    /// let mut device = Device::default();
+    /// device.kind = DeviceType::ROUTER;
+    /// assert!(device.supports_trait("Reboot"));
+    /// assert!(device.supports_trait("action.devices.traits.Reboot"));
+    /// assert!(!device.supports_trait("OnOff"));
+    /// ```
+    pub fn supports_trait(&self, trait_name: &str) -> bool {
+        let trait_name = trait_name.trim_start_matches("action.devices.traits.");
+        self.get_google_device_traits()
+            .iter()
+            .any(|device_trait| device_trait.trim_start_matches("action.devices.traits.") == trait_name)
+    }
+
+    /// Checks this device against what google wants in a SYNC response, so a malformed device is caught before it is
+    /// sent: it needs an id, a name, a device type and at least one trait google knows, and its attributes need every
+    /// attribute its traits require (ie `volumeMaxLevel` for Volume).
+    /// # Example
+    /// ```
+    /// use aa_models::device::{Device, DeviceType};
+    /// let mut device = Device::default();
+    /// device.guid = String::from("test_switch");
+    /// assert!(device.validate_sync().is_ok());
+    /// device.guid = String::new();
+    /// device.name = String::new();
+    /// assert_eq!(2, device.validate_sync().unwrap_err().len());
     /// ```
     /// # Return
-    /// The hardware in a nice string format.
-    pub fn get_google_device_hardware(&self) -> &str {
-        match self.hardware {
-            HardwareType::ARDUINO => "Arduino",
-            HardwareType::PI => "Raspberry Pi",
-            HardwareType::OTHER => "Other",
-            HardwareType::LG => "LG",
+    /// Ok if google should accept this device, or every problem found with it.
+    pub fn validate_sync(&self) -> Result<(), Vec<String>> {
+        let mut problems = vec![];
+        if self.guid.trim().is_empty() {
+            problems.push(String::from("The device has no id"));
+        }
+        if self.get_name().trim().is_empty() {
+            problems.push(String::from("The device has no name"));
+        }
+        if !self.get_google_device_type().starts_with("action.devices.types.") {
+            problems.push(format!("{} is not a google device type", self.get_google_device_type()));
+        }
+        let traits = self.get_google_device_traits();
+        if traits.is_empty() {
+            problems.push(String::from("The device has no traits"));
+        }
+        let attributes = self.get_attributes();
+        for (index, trait_name) in traits.iter().enumerate() {
+            if traits[..index].contains(trait_name) {
+                problems.push(format!("{} is listed more than once", trait_name));
+                continue;
+            }
+            let google_trait = match GoogleTrait::ALL.iter().find(|google_trait| google_trait.as_str() == *trait_name) {
+                Some(google_trait) => google_trait,
+                None => {
+                    problems.push(format!("{} is not a google trait", trait_name));
+                    continue;
+                }
+            };
+            for attribute in google_trait.required_attributes() {
+                if attributes.get(*attribute).map_or(true, Value::is_null) {
+                    problems.push(format!("{} needs the {} attribute", trait_name, attribute));
+                }
+            }
+        }
+        if problems.is_empty() {
+            Ok(())
+        } else {
+            Err(problems)
         }
     }
 
-    /// Gets the name of this device.
-    ///
-    /// # Examples
-    ///
+    /// Checks whether or not this device is on (or open, for garage doors). The state is stored either as a plain bool
+    /// or as an object with an `on` field, depending on the device.
+    /// # Example
     /// ```
-    /// use aa_models::device;
-    ///
-    /// let device = device::get_device_from_guid(&String::from("test_switch"));
-    /// println!("{}",device.get_name());
-    /// assert_eq!("Test Switch",device.get_name());
+    /// use aa_models::device::Device;
+    /// use serde_json::json;
+    /// let mut device = Device::default();
+    /// device.last_state = json!(true);
+    /// assert!(device.is_on());
+    /// device.last_state = json!({"on": false, "brightness": 23});
+    /// assert!(!device.is_on());
     /// ```
-    pub fn get_name(&self) -> &String {
-        if &self.name == "" {
-            return &self.guid;
+    /// # Return
+    /// True if the device is on, false if it is off or its state is unknown.
+    pub fn is_on(&self) -> bool {
+        match &self.last_state {
+            Value::Bool(on) => *on,
+            state => state.get("on").and_then(Value::as_bool).unwrap_or(false),
         }
-        return &self.name;
     }
 
-    /// Checks whether or not this device is online by pinging its IP address.
-    ///
-    /// # Examples
-    ///
+    /// Gets the temperature in the last state of this device in Celsius, converted from the unit the device reports in.
+    /// # Example
     /// ```
-    /// use aa_models::device;
-    /// // Test switch as an IP of 127.0.0.1
-    /// let device = device::get_device_from_guid(&String::from("test_switch"));
-    /// let dev_online = device.is_online();
-    /// println!("{}",dev_online);
-    /// assert!(dev_online);
+    /// use aa_models::device::{Device, TemperatureUnit};
+    /// use serde_json::json;
+    /// let mut device = Device::default();
+    /// device.last_state = json!({"temperature": 73});
+    /// device.temperature_unit = TemperatureUnit::F;
+    /// assert_eq!(Some(22.8), device.temperature_celsius());
     /// ```
-    ///
     /// # Return
-    /// True if the ping was successful.
-    pub fn is_online(&self) -> bool {
-        let mut cmd = Command::new("ping");
-        cmd.stdout(std::process::Stdio::null())
-            .stderr(std::process::Stdio::null())
-            .arg(&self.ip)
-            .args(["-W", "1", "-c", "1"])
-            .status()
-            .unwrap()
-            .success()
+    /// The temperature in Celsius, rounded to one decimal, or None if the device doesn't report one.
+    pub fn temperature_celsius(&self) -> Option<f64> {
+        let temperature = self.last_state[capabilities::TEMPERATURE].as_f64()?;
+        Some(self.temperature_unit.to_celsius(temperature))
     }
-}
+
+    /// Gets the brightness in the last state of this device as a percent, converted from the scale the device reports
+    /// it on (see `brightness_scale`).
+    /// # Example
+    /// ```
+    /// use aa_models::device::{BrightnessScale, Device};
+    /// use serde_json::json;
+    /// let mut device = Device::default();
+    /// device.last_state = json!({"on": true, "brightness": 23});
+    /// assert_eq!(Some(23), device.brightness_percent());
+    /// device.brightness_scale = BrightnessScale::Byte;
+    /// device.last_state = json!({"on": true, "brightness": 255});
+    /// assert_eq!(Some(100), device.brightness_percent());
+    /// ```
+    /// # Return
+    /// The brightness from 0 to 100, or None if the device doesn't report one.
+    pub fn brightness_percent(&self) -> Option<u8> {
+        let brightness = self.last_state["brightness"].as_f64()?;
+        Some(self.brightness_scale.to_percent(brightness))
+    }
+
+    /// Gets the state of this device in one shape per device type, no matter how it was stored: on/off devices (and
+    /// TV's) always get an object with an `on` field, a legacy bare bool state becoming `{"on": <bool>}`. UPS
+    /// batteries and sensors keep the readings they reported, or get an empty object if they have none.
+    /// # Example
+    /// ```
+    /// use aa_models::device::{Device, DeviceType};
+    /// use serde_json::json;
+    /// let mut device = Device::default();
+    /// device.kind = DeviceType::LIGHT;
+    /// device.last_state = json!(true);
+    /// assert_eq!(json!({"on": true}), device.normalized_state());
+    /// device.last_state = json!({"brightness": 23});
+    /// assert_eq!(json!({"on": false, "brightness": 23}), device.normalized_state());
+    /// ```
+    /// # Return
+    /// The state of this device.
+    pub fn normalized_state(&self) -> Value {
+        let mut state = match &self.last_state {
+            Value::Object(..) => self.last_state.clone(),
+            _ => json!({}),
+        };
+        if self.kind != DeviceType::BATTERY && self.kind != DeviceType::SENSOR {
+            state["on"] = Value::Bool(self.is_on());
+        }
+        state
+    }
+
+    /// Checks whether or not this device is a light that reports a color in its state, ie
+    /// `{"on": true, "color": {"spectrumRGB": 16711680}}`
+    /// # Return
+    /// True if this is a light with color data.
+    pub fn has_color(&self) -> bool {
+        self.kind == DeviceType::LIGHT && self.last_state.get("color").map_or(false, Value::is_object)
+    }
+
+    /// Gets the RGB color of this light, for the `color.spectrumRGB` field google uses.
+    /// # Example
+    /// ```
+    /// use aa_models::device::{Device, DeviceType};
+    /// use serde_json::json;
+    /// let mut device = Device::default();
+    /// device.kind = DeviceType::LIGHT;
+    /// device.last_state = json!({"on": true, "color": {"spectrumRGB": 16711680}});
+    /// assert_eq!(Some(16711680), device.get_color_spectrum_rgb());
+    /// ```
+    /// # Return
+    /// The color as a single RGB integer, or None if this light has no color.
+    pub fn get_color_spectrum_rgb(&self) -> Option<u64> {
+        if !self.has_color() {
+            return None;
+        }
+        self.last_state["color"]["spectrumRGB"].as_u64()
+    }
+
+    /// Gets this device as JSON that is safe to hand to clients: everything but its IP and MAC addresses, the user it
+    /// belongs to, its PIN and its auth token.
+    /// # Example
+    /// ```
+    /// use aa_models::device::Device;
+    /// use aa_models::transport::AuthToken;
+    /// use serde_json::json;
+    /// let mut device = Device::default();
+    /// device.guid = String::from("test_switch");
+    /// device.ip = String::from("10.0.0.5");
+    /// device.useruuid = String::from("eoqBGbi9AHUxtOrL3xpJZDQotGP2");
+    /// device.auth_token = Some(AuthToken::new("s3cr3t"));
+    /// device.last_state = json!(true);
+    /// let public = device.to_public_json();
+    /// assert_eq!("test_switch", public["guid"]);
+    /// assert_eq!(true, public["last_state"]);
+    /// for field in ["ip", "useruuid", "auth_token"].iter() {
+    ///     assert!(public.get(field).is_none(), "{}", field);
+    /// }
+    /// ```
+    pub fn to_public_json(&self) -> Value {
+        let mut serialized = serde_json::to_value(&self).unwrap();
+        if let Some(fields) = serialized.as_object_mut() {
+            for field in PRIVATE_FIELDS.iter() {
+                fields.remove(*field);
+            }
+        }
+        serialized
+    }
+
+    /// Gets the fields google cares about that differ between this device and the given one: `last_state`, `name`
+    /// and `nicknames`. Volatile fields (ie `last_seen`) are ignored, the order of the keys in the states doesn't
+    /// matter, and names and nicknames are compared the way they are sent to google.
+    /// # Example
+    /// ```
+    /// use aa_models::device::Device;
+    /// use serde_json::json;
+    /// let mut before = Device::default();
+    /// before.last_state = json!({"on": true, "brightness": 20});
+    /// let mut after = before.clone();
+    /// after.last_state = json!({"brightness": 20, "on": true});
+    /// assert!(!before.state_differs(&after));
+    /// after.name = String::from("Lamp");
+    /// assert_eq!(vec!["name"], before.diff(&after));
+    /// ```
+    /// # Return
+    /// The names of the fields that differ, empty if nothing changed.
+    pub fn diff(&self, other: &Device) -> Vec<&'static str> {
+        let mut changed = vec![];
+        if self.last_state != other.last_state {
+            changed.push("last_state");
+        }
+        if self.get_name() != other.get_name() {
+            changed.push("name");
+        }
+        if self.get_nicknames() != other.get_nicknames() {
+            changed.push("nicknames");
+        }
+        changed
+    }
+
+    /// Checks whether or not anything google cares about differs between this device and the given one, see `diff`.
+    /// # Return
+    /// True if the state, the name or the nicknames changed.
+    pub fn state_differs(&self, other: &Device) -> bool {
+        !self.diff(other).is_empty()
+    }
+
+    /// Gets the result of an EXECUTE intent for this device, as google wants it in the `commands` of the response.
+    /// Please see:
+    /// https://developers.google.com/assistant/smarthome/reference/intent/execute
+    /// for more information on how this JSON looks like. The states are always the current states of the device, and
+    /// a failed command is reported as `deviceOffline` when the device can't be reached, `hardError` otherwise.
+    /// # Example
+    /// ```
+    /// use aa_models::device::{Device, DeviceType};
+    /// use serde_json::json;
+    /// let mut device = Device::default();
+    /// device.guid = String::from("test_switch");
+    /// device.last_state = json!(true);
+    /// let result = device.execute_result(true);
+    /// assert_eq!(json!(["test_switch"]), result["ids"]);
+    /// assert_eq!("SUCCESS", result["status"]);
+    /// assert_eq!(true, result["states"]["on"]);
+    /// ```
+    /// # Params
+    /// * `success` Whether or not the command worked.
+    pub fn execute_result(&self, success: bool) -> Value {
+        let mut result = json!({
+            "ids": [self.guid],
+            "status": if success { "SUCCESS" } else { "ERROR" },
+            "states": self.google_query_json()
+        });
+        if !success {
+            result["errorCode"] = Value::from(if self.is_online() { "hardError" } else { "deviceOffline" });
+        }
+        result
+    }
+
+    /// Gets the result of an EXECUTE intent for this device from what `execute_command` returned. A command that
+    /// needs secondary verification is answered with the `challengeNeeded` google asks the user with, any other error
+    /// the same way as `execute_result(false)`.
+    /// # Example
+    /// ```
+    /// use aa_models::command::ChallengeType;
+    /// use aa_models::device::Device;
+    /// use aa_models::error::DeviceError;
+    /// let device = Device::default();
+    /// let result = device.execute_response(&Err(DeviceError::ChallengeNeeded(ChallengeType::PinNeeded)));
+    /// assert_eq!("challengeNeeded", result["errorCode"]);
+    /// assert_eq!("pinNeeded", result["challengeNeeded"]["type"]);
+    /// ```
+    pub fn execute_response(&self, result: &Result<(), DeviceError>) -> Value {
+        match result {
+            Err(DeviceError::ChallengeNeeded(challenge)) => json!({
+                "ids": [self.guid],
+                "status": "ERROR",
+                "errorCode": "challengeNeeded",
+                "challengeNeeded": { "type": challenge.as_str() }
+            }),
+            result => self.execute_result(result.is_ok()),
+        }
+    }
+
+    /// Checks that the given challenge verifies the given command, for devices google wants secondary verification
+    /// for. Garages need their PIN when they have one, and to be acknowledged otherwise.
+    /// # Return
+    /// Ok if the command may run, or `DeviceError::ChallengeNeeded` with the challenge the user has to answer.
+    fn verify_challenge(&self, command: &str, challenge: Option<&Challenge>) -> Result<(), DeviceError> {
+        if self.kind != DeviceType::GARAGE || command == command::REBOOT {
+            return Ok(());
+        }
+        let pin = match &self.pin {
+            Some(pin) => pin,
+            None if challenge.map_or(false, Challenge::ack) => return Ok(()),
+            None => return Err(DeviceError::ChallengeNeeded(ChallengeType::AckNeeded)),
+        };
+        match challenge.and_then(Challenge::pin) {
+            Some(given) if pin.matches(given) => Ok(()),
+            Some(..) => Err(DeviceError::ChallengeNeeded(ChallengeType::ChallengeFailedPinNeeded)),
+            None => Err(DeviceError::ChallengeNeeded(ChallengeType::PinNeeded)),
+        }
+    }
+
+    /// Runs a command from a google EXECUTE intent on this device. TV's, SQLSprinkler hosts and zones and routers are
+    /// sent the command, every other device gets its new state stored in firebase. On success, `last_state` holds the
+    /// new state, so `execute_response` can be answered with right away.
+    /// # Example
+    /// ```
+    /// use aa_models::command::{Challenge, CommandParams, ChallengeType, OPEN_CLOSE, SET_VOLUME};
+    /// use aa_models::device::{Device, DeviceType};
+    /// use aa_models::error::DeviceError;
+    /// use serde_json::json;
+    /// let mut device = Device::default();
+    /// let params = CommandParams::from_value(&json!({"volumeLevel": 20})).unwrap();
+    /// // Switches have no volume.
+    /// assert!(matches!(device.execute_command(SET_VOLUME, &params, None), Err(DeviceError::Unsupported(_))));
+    ///
+    /// // Garages have to be acknowledged before they are opened.
+    /// device.kind = DeviceType::GARAGE;
+    /// let params = CommandParams::from_value(&json!({"openPercent": 100})).unwrap();
+    /// assert!(matches!(
+    ///     device.execute_command(OPEN_CLOSE, &params, Some(&Challenge::default())),
+    ///     Err(DeviceError::ChallengeNeeded(ChallengeType::AckNeeded))
+    /// ));
+    /// ```
+    /// # Params
+    /// * `command` The name of the command, ie `action.devices.commands.OnOff` (see the constants in `command`).
+    /// * `params` The params google sent with the command.
+    /// * `challenge` The secondary verification google sent with the command, if any.
+    /// # Return
+    /// Ok if the command was run, `DeviceError::Unsupported` if this device doesn't have the trait for the command,
+    /// `DeviceError::ChallengeNeeded` if the command needs secondary verification that wasn't given (or was wrong),
+    /// `DeviceError::MissingField` if a param the command needs wasn't sent, or the error from the device or firebase.
+    pub fn execute_command(
+        &mut self,
+        command: &str,
+        params: &CommandParams,
+        challenge: Option<&Challenge>,
+    ) -> Result<(), DeviceError> {
+        match command::required_trait(command) {
+            Some(google_trait) if self.supports(google_trait) => {}
+            _ => return Err(DeviceError::Unsupported(command.to_string())),
+        }
+        self.verify_challenge(command, challenge)?;
+        match command {
+            command::ON_OFF => {
+                let on = params.on().ok_or_else(|| DeviceError::MissingField(String::from("on")))?;
+                self.set_on(on)
+            }
+            // Garages store whether or not they are open as their on state.
+            command::OPEN_CLOSE => {
+                let open_percent = params
+                    .open_percent()
+                    .ok_or_else(|| DeviceError::MissingField(String::from("openPercent")))?;
+                self.set_on(open_percent > 0)
+            }
+            // Zones are started and stopped the same way they are turned on and off.
+            command::START_STOP => {
+                let start = params.start().ok_or_else(|| DeviceError::MissingField(String::from("start")))?;
+                self.set_on(start)
+            }
+            command::REBOOT => self.reboot(),
+            command::BRIGHTNESS_ABSOLUTE => {
+                let brightness = params
+                    .brightness()
+                    .ok_or_else(|| DeviceError::MissingField(String::from("brightness")))?;
+                self.set_brightness_percent(brightness);
+                self.update_state(self.last_state.clone())
+            }
+            command::COLOR_ABSOLUTE => {
+                let rgb = params
+                    .spectrum_rgb()
+                    .ok_or_else(|| DeviceError::MissingField(String::from("color.spectrumRGB")))?;
+                self.set_color_spectrum_rgb(rgb);
+                self.update_state(self.last_state.clone())
+            }
+            #[cfg(feature = "tv")]
+            command::SET_VOLUME => {
+                let level = params
+                    .volume_level()
+                    .ok_or_else(|| DeviceError::MissingField(String::from("volumeLevel")))?;
+                self.expect_accepted(command, tv::set_volume_state(tv::SetVolState(level)))?;
+                self.last_state["volume"] = Value::from(level);
+                Ok(())
+            }
+            #[cfg(feature = "tv")]
+            command::VOLUME_RELATIVE => {
+                let steps = params
+                    .relative_steps()
+                    .ok_or_else(|| DeviceError::MissingField(String::from("relativeSteps")))?;
+                self.expect_accepted(command, tv::set_volume_relative(self, steps))
+            }
+            #[cfg(feature = "tv")]
+            command::MUTE => {
+                let mute = params.mute().ok_or_else(|| DeviceError::MissingField(String::from("mute")))?;
+                self.expect_accepted(command, tv::set_mute_state(tv::SetMuteState(mute)))?;
+                self.last_state["muted"] = Value::from(mute);
+                Ok(())
+            }
+            #[cfg(feature = "tv")]
+            command::SET_INPUT => {
+                let input = params
+                    .new_input()
+                    .ok_or_else(|| DeviceError::MissingField(String::from("newInput")))?;
+                self.expect_accepted(command, tv::set_input_state(tv::SetInputState(input.to_string())))?;
+                self.last_state["currentInput"] = Value::from(input);
+                Ok(())
+            }
+            _ => Err(DeviceError::Unsupported(command.to_string())),
+        }
+    }
+
+    /// Turns this device off if it is on, and on otherwise (including when its state is unknown), the same way the
+    /// OnOff command does.
+    /// # Example
+    /// ```
+    /// use aa_models::device::{Device, DeviceType};
+    /// use aa_models::error::DeviceError;
+    /// let mut device = Device::default();
+    /// device.kind = DeviceType::GARAGE;
+    /// // Garages are opened and closed, not toggled.
+    /// assert!(matches!(device.toggle(), Err(DeviceError::Unsupported(_))));
+    /// ```
+    /// # Return
+    /// Ok if the device was toggled, `DeviceError::Unsupported` if it can't be turned on and off, or the error from the
+    /// device or firebase.
+    pub fn toggle(&mut self) -> Result<(), DeviceError> {
+        if !self.supports(GoogleTrait::OnOff) {
+            return Err(DeviceError::Unsupported(String::from("toggling")));
+        }
+        let on = !self.is_on();
+        self.set_on(on)
+    }
+
+    /// Turns this device on or off, sending it to the devices that are controlled directly and storing it for the rest.
+    fn set_on(&mut self, on: bool) -> Result<(), DeviceError> {
+        match self.kind {
+            #[cfg(feature = "tv")]
+            DeviceType::TV => self.expect_accepted(command::ON_OFF, tv::set_power_state(on))?,
+            #[cfg(feature = "sqlsprinkler")]
+            DeviceType::SPRINKLER => {
+                let toggle = self
+                    .as_zone_toggle(on)
+                    .ok_or_else(|| DeviceError::MissingField(String::from("id")))?;
+                let request = HostRequest::for_device(self);
+                try_set_zone_with_transport(&IsahcTransport, self.ip.clone(), toggle.state, toggle.id, &request)?;
+            }
+            #[cfg(feature = "sqlsprinkler")]
+            DeviceType::SqlSprinklerHost => {
+                let request = HostRequest::for_device(self);
+                try_set_system_with_transport(&IsahcTransport, self.ip.clone(), on, &request)?
+            }
+            _ => {
+                let mut state = self.last_state.clone();
+                if state.is_object() {
+                    state["on"] = Value::from(on);
+                } else {
+                    state = Value::from(on);
+                }
+                self.update_state(state.clone())?;
+                self.last_state = state;
+            }
+        }
+        if self.last_state.is_object() {
+            self.last_state["on"] = Value::from(on);
+        }
+        Ok(())
+    }
+
+    /// Turns whether or not a device accepted a command into an error when it didn't.
+    #[cfg(feature = "tv")]
+    fn expect_accepted(&self, command: &str, accepted: bool) -> Result<(), DeviceError> {
+        if accepted {
+            Ok(())
+        } else {
+            Err(DeviceError::Network(format!("{} did not accept {}", self.guid, command)))
+        }
+    }
+
+    /// Gets the request for turning this SQLSprinkler zone on or off.
+    /// # Example
+    /// ```
+    /// use aa_models::device::{Device, DeviceType};
+    /// use serde_json::json;
+    /// let mut device = Device::default();
+    /// assert!(device.as_zone_toggle(true).is_none());
+    /// device.kind = DeviceType::SPRINKLER;
+    /// device.last_state = json!({"on": false, "id": 4});
+    /// let toggle = device.as_zone_toggle(true).unwrap();
+    /// assert_eq!((4, true), (toggle.id, toggle.state));
+    /// ```
+    /// # Return
+    /// The request, or None if this device is not a zone or its state has no zone id.
+    #[cfg(feature = "sqlsprinkler")]
+    pub fn as_zone_toggle(&self, state: bool) -> Option<ZoneToggle> {
+        if self.kind != DeviceType::SPRINKLER {
+            return None;
+        }
+        let id = self.last_state["id"].as_i64()?;
+        Some(ZoneToggle { id, state })
+    }
+
+    /// Gets the faults this device reported in its state, in the form google wants for the `currentStatusReport` of
+    /// the StatusReport trait. Right now only SQLSprinkler zones report faults.
+    /// # Return
+    /// A list of status reports, empty if the device has no faults.
+    pub fn get_status_report(&self) -> Vec<Value> {
+        let faults = match self.last_state.get("faults").and_then(Value::as_array) {
+            Some(faults) => faults,
+            None => return vec![],
+        };
+        faults
+            .iter()
+            .filter_map(|fault| {
+                let code = fault.get("code")?.as_str()?;
+                let blocking = fault.get("blocking")?.as_bool()?;
+                Some(json!({
+                    "blocking": blocking,
+                    "deviceTarget": self.guid,
+                    "priority": if blocking { 0 } else { 1 },
+                    "statusCode": code
+                }))
+            })
+            .collect()
+    }
+
+    /// Sets the brightness of this light in its state, converted from a percent to the scale the light reports it on
+    /// (see `brightness_scale`). A light that only stored a bool state is converted to
+    /// `{"on": <state>, "brightness": ...}`.
+    /// # Example
+    /// ```
+    /// use aa_models::device::{BrightnessScale, Device, DeviceType};
+    /// use serde_json::json;
+    /// let mut device = Device::default();
+    /// device.kind = DeviceType::LIGHT;
+    /// device.brightness_scale = BrightnessScale::Byte;
+    /// device.last_state = json!(true);
+    /// device.set_brightness_percent(50);
+    /// assert_eq!(json!({"on": true, "brightness": 128}), device.last_state);
+    /// assert_eq!(Some(50), device.brightness_percent());
+    /// ```
+    /// # Params
+    /// * `percent` The brightness from 0 to 100, as given in google's `brightness` command param.
+    pub fn set_brightness_percent(&mut self, percent: u8) {
+        if !self.last_state.is_object() {
+            let on = self.last_state.as_bool().unwrap_or(false);
+            self.last_state = json!({ "on": on });
+        }
+        self.last_state["brightness"] = Value::from(self.brightness_scale.from_percent(percent));
+    }
+
+    /// Sets the RGB color of this light in its state. A light that only stored a bool state is converted to
+    /// `{"on": <state>, "color": {...}}`.
+    /// # Params
+    /// * `rgb` The color as a single RGB integer, as given in google's `color.spectrumRGB` command param.
+    pub fn set_color_spectrum_rgb(&mut self, rgb: u64) {
+        if !self.last_state.is_object() {
+            let on = self.last_state.as_bool().unwrap_or(false);
+            self.last_state = json!({ "on": on });
+        }
+        self.last_state["color"] = json!({ "spectrumRGB": rgb });
+    }
+
+    /// Gets the hardware type for google home
+    ///
+    /// # Examples
+    /// This shows getting the hardware type for an "Other" device
+    /// ```
+    /// use aa_models::device;
+    /// let device = device::get_device_from_guid(&String::from("test_switch"));
+    /// println!("{}",device.get_google_device_hardware());
+    /// assert_eq!("Other",device.get_google_device_hardware());
+    /// ```
+    /// This shows getting the hardware type for an Arduino device
+    /// ```
+    /// use aa_models::device;
+    /// let device = device::get_device_from_guid(&String::from("test_light"));
+    /// println!("{}",device.get_google_device_hardware());
+    /// assert_eq!("Arduino",device.get_google_device_hardware());
+    /// ```
+    /// # Return
+    /// The hardware in a nice string format.
+    pub fn get_google_device_hardware(&self) -> &str {
+        match self.hardware {
+            HardwareType::ARDUINO => "Arduino",
+            HardwareType::PI => "Raspberry Pi",
+            HardwareType::OTHER => "Other",
+            HardwareType::LG => "LG",
+        }
+    }
+
+    /// Gets the name of this device, without any surrounding whitespace. A device with a blank name is named by its
+    /// guid instead, so it never shows up without a label.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use aa_models::device;
+    ///
+    /// let device = device::get_device_from_guid(&String::from("test_switch"));
+    /// println!("{}",device.get_name());
+    /// assert_eq!("Test Switch",device.get_name());
+    ///
+    /// let mut blank = device::Device::default();
+    /// blank.guid = String::from("blank_device");
+    /// blank.name = String::from("   ");
+    /// assert_eq!("blank_device", blank.get_name());
+    /// ```
+    pub fn get_name(&self) -> &str {
+        let name = self.name.trim();
+        if name.is_empty() {
+            return &self.guid;
+        }
+        name
+    }
+
+    /// Gets the nicknames of this device that are worth sending to google: blank nicknames, repeated nicknames and
+    /// nicknames that are just the name of the device are left out.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use aa_models::device;
+    ///
+    /// let mut device = device::Device::default();
+    /// assert!(device.get_nicknames().is_empty());
+    ///
+    /// device.name = String::from("Lamp");
+    /// device.nicknames = vec![
+    ///     String::from(" Desk Lamp "),
+    ///     String::from("Desk Lamp"),
+    ///     String::from("Lamp"),
+    ///     String::from(""),
+    /// ];
+    /// assert_eq!(vec!["Desk Lamp"], device.get_nicknames());
+    /// ```
+    pub fn get_nicknames(&self) -> Vec<&str> {
+        let name = self.get_name();
+        let mut nicknames: Vec<&str> = vec![];
+        for nickname in &self.nicknames {
+            let nickname = nickname.trim();
+            if nickname.is_empty() || nickname == name || nicknames.contains(&nickname) {
+                continue;
+            }
+            nicknames.push(nickname);
+        }
+        nicknames
+    }
+
+    /// Asks the device for its model over HTTP and stores it, so that it is reported to google instead of the generic
+    /// hardware type.
+    /// # Example
+    /// ```
+    /// use aa_models::device;
+    /// let mut device = device::get_device_from_guid(&String::from("test_light"));
+    /// if device.refresh_model().is_ok() {
+    ///     println!("{:?}",device.model);
+    /// }
+    /// ```
+    /// # Return
+    /// Ok if the model was read and saved, `DeviceError::Unsupported` if this device has no HTTP API, or an error if the
+    /// device or firebase could not be reached.
+    pub fn refresh_model(&mut self) -> Result<(), DeviceError> {
+        let url = match self.get_api_url(String::from("model")) {
+            Some(url) => url,
+            None => return Err(DeviceError::Unsupported(String::from("reading the model"))),
+        };
+        let body = self.api_get(&url)?;
+        let model = body.trim();
+        if model.is_empty() {
+            return Ok(());
+        }
+        self.model = Some(model.to_string());
+        self.update_fields(&["model"])
+    }
+
+    /// Gets the icon the app shows for this device.
+    /// # Example
+    /// ```
+    /// use aa_models::device::Device;
+    /// let mut device = Device::default();
+    /// device.set_icon(Some(String::from("lightbulb")));
+    /// device.set_color(Some(String::from("blue")));
+    /// assert_eq!(Some(&String::from("lightbulb")), device.get_icon());
+    /// assert_eq!(Some(&String::from("blue")), device.get_color());
+    /// ```
+    pub fn get_icon(&self) -> Option<&String> {
+        self.icon.as_ref()
+    }
+
+    /// Sets the icon the app shows for this device, None to use the default icon.
+    pub fn set_icon(&mut self, icon: Option<String>) {
+        self.icon = icon;
+    }
+
+    /// Gets the color of the tile the app shows for this device.
+    pub fn get_color(&self) -> Option<&String> {
+        self.color.as_ref()
+    }
+
+    /// Sets the color of the tile the app shows for this device, None to use the default color.
+    pub fn set_color(&mut self, color: Option<String>) {
+        self.color = color;
+    }
+
+    /// Reboots this router through its `reboot` endpoint. See `router::reboot` to use another endpoint.
+    /// # Example
+    /// ```
+    /// use aa_models::device;
+    /// let device = device::get_device_from_guid(&String::from("test_router"));
+    /// if let Err(e) = device.reboot() {
+    ///     println!("{}",e);
+    /// }
+    /// ```
+    /// # Return
+    /// Ok if the router is rebooting, or an error if it isn't a router or refused to reboot.
+    pub fn reboot(&self) -> Result<(), DeviceError> {
+        router::reboot(self, router::DEFAULT_REBOOT_ENDPOINT)
+    }
+
+    /// Gets the typed status of this UPS out of its state.
+    /// # Example
+    /// ```
+    /// use aa_models::device::{Device, DeviceType};
+    /// use serde_json::json;
+    /// let mut device = Device::default();
+    /// device.kind = DeviceType::BATTERY;
+    /// device.last_state = json!({"battery.charge": 100, "ups.status": "OL"});
+    /// assert_eq!(100.0, device.ups_status().unwrap().charge_percent);
+    /// ```
+    /// # Return
+    /// The status of the UPS, or None if this isn't a battery or it has no status yet.
+    #[cfg(feature = "battery")]
+    pub fn ups_status(&self) -> Option<battery::UpsStatus> {
+        if self.kind != DeviceType::BATTERY {
+            return None;
+        }
+        battery::UpsStatus::from_value(&self.last_state)
+    }
+
+    /// Checks whether or not this UPS needs attention, because it is running on battery or its charge dropped below the
+    /// given threshold.
+    /// # Example
+    /// ```
+    /// use aa_models::device::{Device, DeviceType};
+    /// use serde_json::json;
+    /// let mut device = Device::default();
+    /// // Devices that aren't batteries are never critical.
+    /// assert!(!device.is_ups_critical(20.0));
+    /// device.kind = DeviceType::BATTERY;
+    /// device.last_state = json!({"battery.charge": 15, "ups.status": "OL CHRG"});
+    /// assert!(device.is_ups_critical(20.0));
+    /// ```
+    /// # Params
+    /// * `threshold_percent` The charge, from 0 to 100, under which the UPS is critical.
+    /// # Return
+    /// True if this is a battery that is on battery power or under the threshold.
+    #[cfg(feature = "battery")]
+    pub fn is_ups_critical(&self, threshold_percent: f32) -> bool {
+        match self.ups_status() {
+            Some(status) => status.on_battery || status.charge_percent < threshold_percent,
+            None => false,
+        }
+    }
+
+    /// Gets how often this device should report its state, as configured on the server.
+    /// # Example
+    /// ```
+    /// use aa_models::device::{Device, ReportingConfig, ReportingMode};
+    /// use std::time::Duration;
+    /// let mut device = Device::default();
+    /// assert_eq!(None, device.reporting_interval());
+    /// device.reporting = Some(ReportingConfig { interval: 120, mode: ReportingMode::POLL });
+    /// assert_eq!(Some(Duration::from_secs(120)), device.reporting_interval());
+    /// ```
+    /// # Return
+    /// The reporting interval, or None if the server has no reporting configuration for this device.
+    pub fn reporting_interval(&self) -> Option<Duration> {
+        self.reporting
+            .as_ref()
+            .map(|reporting| Duration::from_secs(reporting.interval as u64))
+    }
+
+    /// Gets how often this device should be polled for its state: its own `poll_interval_secs`, otherwise the
+    /// reporting interval the server configured for it (see `reporting_interval`).
+    /// # Example
+    /// ```
+    /// use aa_models::device::{Device, ReportingConfig, ReportingMode, DEFAULT_POLL_INTERVAL};
+    /// use std::time::Duration;
+    /// let mut device = Device::default();
+    /// assert_eq!(DEFAULT_POLL_INTERVAL, device.poll_interval());
+    /// device.reporting = Some(ReportingConfig { interval: 120, mode: ReportingMode::POLL });
+    /// assert_eq!(Duration::from_secs(120), device.poll_interval());
+    /// device.poll_interval_secs = Some(30);
+    /// assert_eq!(Duration::from_secs(30), device.poll_interval());
+    /// ```
+    /// # Return
+    /// The polling interval of this device, or `DEFAULT_POLL_INTERVAL` if neither it nor the server has one.
+    pub fn poll_interval(&self) -> Duration {
+        self.poll_interval_secs
+            .map(|secs| Duration::from_secs(secs as u64))
+            .or_else(|| self.reporting_interval())
+            .unwrap_or(DEFAULT_POLL_INTERVAL)
+    }
+
+    /// Checks whether or not this device is due to be polled again.
+    /// # Example
+    /// ```
+    /// use aa_models::device::Device;
+    /// use std::time::{Duration, Instant};
+    /// let mut device = Device::default();
+    /// device.poll_interval_secs = Some(30);
+    /// assert!(!device.poll_due(Instant::now()));
+    /// if let Some(a_minute_ago) = Instant::now().checked_sub(Duration::from_secs(60)) {
+    ///     assert!(device.poll_due(a_minute_ago));
+    /// }
+    /// ```
+    /// # Params
+    /// * `last_polled` When this device was last polled.
+    /// # Return
+    /// True if at least `poll_interval` went by since `last_polled`.
+    pub fn poll_due(&self, last_polled: Instant) -> bool {
+        last_polled.elapsed() >= self.poll_interval()
+    }
+
+    /// Gets how long requests to this device may take before giving up.
+    /// # Example
+    /// ```
+    /// use aa_models::device::{Device, DEFAULT_REQUEST_TIMEOUT};
+    /// use std::time::Duration;
+    /// let mut device = Device::default();
+    /// assert_eq!(DEFAULT_REQUEST_TIMEOUT, device.request_timeout());
+    /// device.request_timeout_secs = Some(15);
+    /// assert_eq!(Duration::from_secs(15), device.request_timeout());
+    /// ```
+    /// # Return
+    /// The timeout of this device, or `DEFAULT_REQUEST_TIMEOUT` if it has none.
+    pub fn request_timeout(&self) -> Duration {
+        self.request_timeout_secs
+            .map_or(DEFAULT_REQUEST_TIMEOUT, Duration::from_secs)
+    }
+
+    /// Checks whether or not this device is online by pinging its IP address. Devices without an IP address of their
+    /// own can't be pinged: a SQLSprinkler zone is online when its host is, and anything else is online when it has
+    /// reported a state.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use aa_models::device;
+    /// use serde_json::json;
+    /// // Test switch as an IP of 127.0.0.1
+    /// let device = device::get_device_from_guid(&String::from("test_switch"));
+    /// let dev_online = device.is_online();
+    /// println!("{}",dev_online);
+    /// assert!(dev_online);
+    ///
+    /// let mut no_ip = device::Device::default();
+    /// no_ip.last_state = json!(null);
+    /// assert!(!no_ip.is_online());
+    /// no_ip.last_state = json!({"on": true});
+    /// assert!(no_ip.is_online());
+    /// ```
+    ///
+    /// # Return
+    /// True if the ping was successful.
+    pub fn is_online(&self) -> bool {
+        if !self.ip.trim().is_empty() {
+            return self.ping().is_some();
+        }
+        if let Some(host_guid) = host_guid_of_zone(&self.guid) {
+            // Only the stored host is read, so checking a zone never asks the host for its state or writes it back.
+            return match read_stored_device(&host_guid) {
+                Ok(host) => !host.ip.trim().is_empty() && host.ping().is_some(),
+                Err(e) => {
+                    debug!("Could not read the host of {}: {}", self.guid, e);
+                    false
+                }
+            };
+        }
+        !self.last_state.is_null()
+    }
+
+    /// Gets the guid of the SQLSprinkler host this zone belongs to, from the guid of the zone.
+    /// # Example
+    /// ```
+    /// use aa_models::device::{Device, DeviceType};
+    /// let mut device = Device::default();
+    /// device.kind = DeviceType::SPRINKLER;
+    /// device.guid = String::from("0b5a2b5c-aabb-4f5e-9c3d-1234567890ab-2");
+    /// assert_eq!(Some(String::from("0b5a2b5c-aabb-4f5e-9c3d-1234567890ab")), device.sqlsprinkler_host_guid());
+    /// device.kind = DeviceType::SWITCH;
+    /// assert_eq!(None, device.sqlsprinkler_host_guid());
+    /// ```
+    /// # Return
+    /// The guid of the host, or None if this device isn't a SQLSprinkler zone.
+    pub fn sqlsprinkler_host_guid(&self) -> Option<String> {
+        if self.kind != DeviceType::SPRINKLER {
+            return None;
+        }
+        host_guid_of_zone(&self.guid)
+    }
+
+    /// Checks whether or not this device is online (see `is_online`), and if it is, records that it was seen now and
+    /// saves that in the backend database.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use aa_models::device;
+    /// let mut device = device::get_device_from_guid(&String::from("test_switch"));
+    /// if device.refresh_online() {
+    ///     assert!(device.last_seen.is_some());
+    /// }
+    /// ```
+    ///
+    /// # Return
+    /// True if the device is online.
+    pub fn refresh_online(&mut self) -> bool {
+        if !self.is_online() {
+            return false;
+        }
+        self.last_seen = Some(Utc::now());
+        if let Err(e) = self.update_fields(&["last_seen"]) {
+            warn!("Could not save when {} was last seen: {}", self.guid, e);
+        }
+        true
+    }
+
+    /// Pings this device's IP address and measures how long it took to respond.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use aa_models::device;
+    /// // Test switch as an IP of 127.0.0.1
+    /// let device = device::get_device_from_guid(&String::from("test_switch"));
+    /// let latency = device.ping();
+    /// println!("{:?}",latency);
+    /// assert!(latency.is_some());
+    /// ```
+    ///
+    /// # Return
+    /// The round trip time of the ping, or None if the device could not be reached.
+    pub fn ping(&self) -> Option<Duration> {
+        // macOS takes the wait time in milliseconds, linux takes it in seconds.
+        let wait_time = if cfg!(target_os = "macos") { "1000" } else { "1" };
+        let output = Command::new("ping")
+            .stderr(std::process::Stdio::null())
+            .arg(&self.ip)
+            .args(["-W", wait_time, "-c", "1"])
+            .output()
+            .ok()?;
+        if !output.status.success() {
+            return None;
+        }
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        Some(parse_ping_time(&stdout).unwrap_or_default())
+    }
+}
+
+/// Parses the round trip time out of the output of `ping`. Linux and macOS both print replies as
+/// `... time=0.045 ms`, some other pings print `time<1ms` for very fast replies.
+/// # Return
+/// The round trip time, or None if there was no time in the output.
+fn parse_ping_time(output: &str) -> Option<Duration> {
+    let start = output.find("time=").or_else(|| output.find("time<"))? + 5;
+    let millis: String = output[start..]
+        .chars()
+        .take_while(|c| c.is_ascii_digit() || *c == '.')
+        .collect();
+    let millis: f64 = millis.parse().ok()?;
+    Some(Duration::from_secs_f64(millis / 1000.0))
+}
 
 /// Gets the device from the database that corresponds to the given UUID.  If the device has the following pattern:
 /// xxxxxxxx-yyy-zzzzzzzzzzzz-n then we will get the device status from the SQLSprinkler host.
 /// # Examples
 ///
-/// This example covers getting a non-SQLSprinkler device
+/// This example covers getting a non-SQLSprinkler device
+/// ```
+/// use aa_models::device;
+/// // Get a test device
+/// let device = device::get_device_from_guid(&String::from("test_switch"));
+/// //...
+/// ```
+/// # Params
+/// * `guid`  The GUID of the device we want to get.
+/// # Return
+/// * A device that corresponds to the given uuid, if there is no match, return a default device. Use
+/// `try_get_device_from_guid` to tell missing devices apart from real ones.
+pub fn get_device_from_guid(guid: &String) -> Device {
+    match try_get_device_from_guid(guid) {
+        Ok(dev) => dev,
+        Err(DeviceError::NotFound(_)) => {
+            debug!("There is no device {}, returning default device", guid);
+            Device::default()
+        }
+        Err(e) => {
+            error!("Could not get device {}: {}", guid, e);
+            Device::default()
+        }
+    }
+}
+
+/// Gets the device from the database that corresponds to the given UUID, like `get_device_from_guid`, but tells why
+/// there is no device instead of returning a default one.
+/// # Example
+/// ```
+/// use aa_models::device;
+/// use aa_models::error::DeviceError;
+/// match device::try_get_device_from_guid(&String::from("no_such_device")) {
+///     Err(DeviceError::NotFound(guid)) => assert_eq!("no_such_device", guid),
+///     other => println!("{:?}", other),
+/// }
+/// ```
+/// # Params
+/// * `guid`  The GUID of the device we want to get.
+/// # Return
+/// * The device, `DeviceError::NotFound` if firebase has nothing stored for the guid, `DeviceError::Deserialize` if
+/// what is stored is not a device, or the error from firebase. SQLSprinkler zones come back from their host, see
+/// `sqlsprinkler::try_get_zone`: `DeviceError::NotFound` if the host or the zone doesn't exist, or the error from the
+/// host.
+pub fn try_get_device_from_guid(guid: &String) -> Result<Device, DeviceError> {
+    #[cfg(feature = "sqlsprinkler")]
+    {
+        if check_if_zone(guid) {
+            return try_get_zone(guid);
+        }
+    }
+
+    Ok(refresh_from_hardware(read_stored_device(guid)?))
+}
+
+/// Asks the given stored device for its current state, for the device types whose state comes from the hardware
+/// (SQLSprinkler hosts, TV's and UPS batteries), and stores what they answered. Every other device is returned as-is.
+fn refresh_from_hardware(mut dev: Device) -> Device {
+    match dev.kind {
+        #[cfg(feature = "sqlsprinkler")]
+        DeviceType::SqlSprinklerHost => {
+            if dev.is_online() {
+                dev = refresh_online_host(dev).0;
+            }
+        }
+        #[cfg(feature = "tv")]
+        DeviceType::TV => {
+            dev = tv::parse_device(dev.clone());
+        }
+        #[cfg(feature = "battery")]
+        DeviceType::BATTERY => {
+            dev = battery::parse_device(dev.clone());
+        }
+        _ => {}
+    }
+    dev
+}
+
+/// Asks the given SQLSprinkler host, which has to be online, for its current state and stores what it answered.
+/// # Return
+/// The host, and the zones it reported along with its state so they don't have to be fetched again. If the host could
+/// not be read, it keeps its cached state and the error is returned instead of the zones.
+#[cfg(feature = "sqlsprinkler")]
+fn refresh_online_host(mut dev: Device) -> (Device, Result<Vec<Zone>, DeviceError>) {
+    let zones = match fetch_host_state(&dev.ip, dev.request_timeout()) {
+        Ok((state, zones)) => {
+            dev.last_state = state;
+            zones
+        }
+        Err(e) => {
+            // A failed read is not the system being off, so the cached state is kept and not written.
+            warn!("Could not get the status of {}, keeping its cached state: {}", dev.guid, e);
+            return (dev, Err(e));
+        }
+    };
+    if let Err(e) = dev.database_update() {
+        warn!("Could not update {}: {}", dev.guid, e);
+    }
+    (dev, Ok(zones))
+}
+
+/// Gets the device stored in firebase for the given guid, without asking the device for its current state. This never
+/// waits on the device itself, so it can be used for devices that may be offline (TV's, SQLSprinkler hosts...). The
+/// device is marked as `stale`, as its state may be out of date. SQLSprinkler zones are not stored in firebase, so
+/// they can't be read this way.
+/// # Example
+///```
+/// use aa_models::device;
+/// let device = device::get_stored_device_from_guid(&String::from("test_switch"));
+/// assert!(device.stale);
+/// ```
+/// # Params
+/// * `guid`  The GUID of the device we want to get.
+/// # Return
+/// * The stored device, or a default device if there is no match or the guid is a zone.
+pub fn get_stored_device_from_guid(guid: &String) -> Device {
+    if is_zone_guid(guid) {
+        return Device::default();
+    }
+    match read_stored_device(guid) {
+        Ok(mut dev) => {
+            dev.stale = true;
+            dev
+        }
+        Err(DeviceError::NotFound(_)) => Device::default(),
+        Err(e) => {
+            error!("Could not get device {}: {}", guid, e);
+            Device::default()
+        }
+    }
+}
+
+/// Reads the device stored in firebase for the given guid.
+/// # Return
+/// The device, `DeviceError::NotFound` if nothing is stored for the guid, `DeviceError::Deserialize` if what is stored
+/// is not a device, or the error from firebase.
+pub(crate) fn read_stored_device(guid: &String) -> Result<Device, DeviceError> {
+    let device_value = fetch_device_value(guid)?;
+    serde_json::from_value(device_value).map_err(|e| {
+        warn!("Could not read device {}: {}", guid, e);
+        DeviceError::Deserialize(e.to_string())
+    })
+}
+
+/// The fields a device document must have to be read by `get_device_from_guid_strict`. Every other field has a
+/// default when it is missing.
+pub const STRICT_REQUIRED_FIELDS: [&str; 5] = ["guid", "kind", "hardware", "last_state", "useruuid"];
+
+/// Gets the device stored in firebase for the given guid, refusing documents that don't match the schema of a device
+/// instead of falling back to a default device like `get_device_from_guid` does. A document must have every field in
+/// `STRICT_REQUIRED_FIELDS`, and no fields that a device doesn't have. The device is returned as it is stored, without
+/// asking the device for its state.
+/// # Example
+///```
+/// use aa_models::device;
+/// let device = device::get_device_from_guid_strict(&String::from("test_switch"));
+/// assert!(device.is_ok());
+/// ```
+/// # Params
+/// * `guid`  The GUID of the device we want to get.
+/// # Return
+/// * The device, `DeviceError::MissingField` or `DeviceError::UnknownField` if the document doesn't match the schema,
+/// `DeviceError::Deserialize` if it is not a device at all, `DeviceError::NotFound` if there is no such device, or the
+/// error from firebase. SQLSprinkler zones are not
+/// stored in firebase, so they are `DeviceError::Unsupported`.
+pub fn get_device_from_guid_strict(guid: &String) -> Result<Device, DeviceError> {
+    if is_zone_guid(guid) {
+        return Err(DeviceError::Unsupported(String::from("strict reads of SQLSprinkler zones")));
+    }
+    let device_value = fetch_device_value(guid)?;
+    let document = match device_value.as_object() {
+        Some(document) => document,
+        None => return Err(DeviceError::Deserialize(format!("{} is not a device", guid))),
+    };
+    if let Some(field) = STRICT_REQUIRED_FIELDS
+        .iter()
+        .find(|field| !document.contains_key(**field))
+    {
+        return Err(DeviceError::MissingField(field.to_string()));
+    }
+    let known_fields = serde_json::to_value(Device::default()).unwrap();
+    if let Some(field) = document.keys().find(|field| known_fields.get(field.as_str()).is_none()) {
+        return Err(DeviceError::UnknownField(field.clone()));
+    }
+    serde_json::from_value(device_value).map_err(|e| DeviceError::Deserialize(e.to_string()))
+}
+
+/// Gets all of the devices that are connected to this user in the database.
+///
+/// # Example
+///```
+/// use aa_models::device;
+///
+/// let device_list = device::get_devices_uuid(&String::from("eoqBGbi9AHUxtOrL3xpJZDQotGP2"));
+/// println!("{:?}",device_list);
+/// ```
+/// # Return
+/// * A `Vec<Device>` containing all of the device information.
+pub fn get_devices_uuid(user_uuid: &String) -> Vec<Device> {
+    let firebase_device_list = get_device_list(user_uuid);
+    device_list_from_firebase(serde_json::to_value(firebase_device_list).unwrap(), None, true)
+}
+
+/// Gets the devices of the given types that are connected to this user in the database. The zones of SQLSprinkler
+/// hosts are included when `SPRINKLER` is asked for, and are not fetched from the hosts otherwise.
+///
+/// # Example
+///```
+/// use aa_models::device;
+/// use aa_models::device::DeviceType;
+///
+/// let uuid = String::from("eoqBGbi9AHUxtOrL3xpJZDQotGP2");
+/// let lights = device::get_devices_uuid_filtered(&uuid, &[DeviceType::LIGHT]);
+/// assert!(lights.iter().all(|light| light.kind == DeviceType::LIGHT));
+/// ```
+/// # Return
+/// * A `Vec<Device>` containing the devices of the given types.
+pub fn get_devices_uuid_filtered(user_uuid: &String, kinds: &[DeviceType]) -> Vec<Device> {
+    let firebase_device_list = get_device_list(user_uuid);
+    device_list_from_firebase(serde_json::to_value(firebase_device_list).unwrap(), Some(kinds), true)
+}
+
+/// Gets all of the devices that are connected to this user in the database, without asking SQLSprinkler hosts for
+/// their zones. This saves a request to every host for callers that don't need the zones, which can be fetched later
+/// with `check_if_device_is_sqlsprinkler_host`.
+///
+/// # Example
+///```
+/// use aa_models::device;
+///
+/// let device_list = device::get_devices_uuid_without_zones(&String::from("eoqBGbi9AHUxtOrL3xpJZDQotGP2"));
+/// println!("{:?}",device_list);
+/// ```
+/// # Return
+/// * A `Vec<Device>` containing the devices of the user, with hosts but no zones.
+pub fn get_devices_uuid_without_zones(user_uuid: &String) -> Vec<Device> {
+    let firebase_device_list = get_device_list(user_uuid);
+    device_list_from_firebase(serde_json::to_value(firebase_device_list).unwrap(), None, false)
+}
+
+/// Gets all the GUIDs belonging to the given user uuid. A list that can't be read is empty, so this must never be used
+/// to change the list and write it back, see `try_get_device_list`.
+/// # Example
+///```
+/// use aa_models::device;
+///
+/// let device_list = device::get_device_list(&String::from("eoqBGbi9AHUxtOrL3xpJZDQotGP2"));
+/// println!("{:?}",device_list);
+/// ```
+/// # Return
+/// * A `Vec<Device>` containing all of the device information.
+pub fn get_device_list(user_uuid: &String) -> Vec<String> {
+    match try_get_device_list(user_uuid) {
+        Ok(list) => list,
+        Err(e) => {
+            error!("Could not get the devices of {}: {}", user_uuid, e);
+            vec![]
+        }
+    }
+}
+
+/// Gets all the GUIDs belonging to the given user uuid, telling apart a user without devices from a list that could
+/// not be read. Anything that changes the list and writes it back has to use this.
+/// # Example
+///```
+/// use aa_models::device;
+///
+/// let device_list = device::try_get_device_list(&String::from("eoqBGbi9AHUxtOrL3xpJZDQotGP2"));
+/// assert!(device_list.is_ok());
+/// ```
+/// # Return
+/// The guids, empty if the user has no devices, `DeviceError::Backend` if firebase answered with an error,
+/// `DeviceError::Deserialize` if what is stored isn't a list of guids, or `DeviceError::Network` if firebase could not
+/// be reached.
+pub fn try_get_device_list(user_uuid: &String) -> Result<Vec<String>, DeviceError> {
+    let response = firebase_users()
+        .at(&user_uuid)
+        .map_err(network)?
+        .at("devices")
+        .map_err(network)?
+        .get()
+        .map_err(network)?;
+    match check_firebase_body(response.code, response.body)? {
+        // Firebase answers with a literal null for users without devices.
+        Value::Null => Ok(vec![]),
+        body => serde_json::from_value(body).map_err(|e| DeviceError::Deserialize(e.to_string())),
+    }
+}
+
+/// Gets the device document stored in firebase for the given guid.
+fn fetch_device_value(guid: &String) -> Result<Value, DeviceError> {
+    let response = firebase_devices()
+        .at(guid)
+        .map_err(network)?
+        .get()
+        .map_err(network)?;
+    match check_firebase_body(response.code, response.body)? {
+        // Firebase answers with a literal null for paths that hold nothing.
+        Value::Null => Err(DeviceError::NotFound(guid.clone())),
+        value => Ok(value),
+    }
+}
+
+/// Makes sure firebase actually answered with data, and not with an error status or an HTML error page (which
+/// proxies in front of firebase answer with on a gateway timeout).
+/// # Return
+/// The body of the response, or `DeviceError::Backend` with the status and the start of the body.
+fn check_firebase_body(code: StatusCode, body: Value) -> Result<Value, DeviceError> {
+    let is_html = match &body {
+        Value::String(text) => text.trim_start().starts_with('<'),
+        _ => false,
+    };
+    if !code.is_success() || is_html {
+        let text = match body {
+            Value::String(text) => text,
+            other => other.to_string(),
+        };
+        return Err(DeviceError::Backend(code, text.chars().take(80).collect()));
+    }
+    Ok(body)
+}
+
+/// Sets the list of devices for the user
+/// # Params
+/// user_uuid: The user uuid we want to update
+/// device_list: The list of device GUIDs.
+/// # Example
+///```
+/// use aa_models::device;
+/// use aa_models::device::{get_device_list,set_device_list};
+/// // Create a copy of the current device list
+/// let backup_list = get_device_list(&String::from("eoqBGbi9AHUxtOrL3xpJZDQotGP2"));
+/// let mut list = get_device_list(&String::from("eoqBGbi9AHUxtOrL3xpJZDQotGP2"));
+/// list.push(String::from("8675309"));
+/// set_device_list(&String::from("eoqBGbi9AHUxtOrL3xpJZDQotGP2"),list.clone());
+/// assert_ne!(&backup_list, &list);
+/// set_device_list(&String::from("eoqBGbi9AHUxtOrL3xpJZDQotGP2"),backup_list.clone());
+/// list = get_device_list(&String::from("eoqBGbi9AHUxtOrL3xpJZDQotGP2"));
+/// assert_eq!(&backup_list,&list);
+/// ```
+pub fn set_device_list(user_uuid: &String, device_list: Vec<String>) -> bool {
+    let list = serde_json::to_value(device_list).unwrap();
+    if is_dry_run() {
+        info!("[dry-run] Setting the devices of {} : {}", user_uuid, list);
+        return true;
+    }
+    let devices = match firebase_users().at(&user_uuid).and_then(|user| user.at("devices")) {
+        Ok(devices) => devices,
+        Err(e) => {
+            error!("Could not find the devices of {}: {}", user_uuid, e);
+            return false;
+        }
+    };
+    match devices.set(list) {
+        Ok(response) => response.code == StatusCode::OK,
+        Err(e) => {
+            error!("Could not set the devices of {}: {}", user_uuid, e);
+            false
+        }
+    }
+}
+
+/// Updates every given device in the backend database with a single multi-location update, so either every device is
+/// written or none are. SQLSprinkler zones aren't stored in firebase, so they are skipped.
+/// # Example
+///```
+/// use aa_models::device;
+///
+/// let devices = vec![
+///     device::get_device_from_guid(&String::from("test_light")),
+///     device::get_device_from_guid(&String::from("test_switch")),
+/// ];
+/// assert!(device::database_update_many(&devices).is_ok());
+/// ```
+/// # Return
+/// Ok if firebase accepted the update, otherwise the same errors as `Device::database_update`.
+pub fn database_update_many(devices: &[Device]) -> Result<(), DeviceError> {
+    let update: serde_json::Map<String, Value> = devices
+        .iter()
+        .filter(|device| !is_zone_guid(&device.guid))
+        .map(|device| (device.guid.clone(), serde_json::to_value(device).unwrap()))
+        .collect();
+    if update.is_empty() {
+        return Ok(());
+    }
+    debug!("Updating {} devices", update.len());
+    if is_dry_run() {
+        info!("[dry-run] Updating devices {:?}", update.keys().collect::<Vec<_>>());
+        return Ok(());
+    }
+    let response = firebase_devices()
+        .update(Value::Object(update))
+        .map_err(network)?;
+    debug!("Firebase responded with: {}", response.code);
+    if response.code != StatusCode::OK {
+        return Err(DeviceError::Firebase(response.code));
+    }
+    Ok(())
+}
+
+/// Adds a device to the given users account.
+/// # Params
+/// user_uuid: The user to add the device to.
+/// device: The device we want to add.
+/// # Example
+///```
+/// use aa_models::device;
+/// use aa_models::device::{get_device_list, set_device_list, DeviceType, add_device};
+///
+/// let uuid = String::from("eoqBGbi9AHUxtOrL3xpJZDQotGP2");
+/// let mut device = device::Device::default();
+/// device.guid = String::from("test-in-testing");
+/// device.kind = DeviceType::LIGHT;
+/// device.useruuid = uuid.clone();
+/// assert!(add_device(&uuid,device).is_ok());
+/// ```
+/// # Return
+/// Ok if the device and the device list of the user were both written, the error of `try_get_device_list` if the
+/// list could not be read, `DeviceError::Firebase` if firebase refused a write, or `DeviceError::Network` if firebase
+/// could not be reached.
+pub fn add_device(user_uuid: &String, mut device: Device) -> Result<(), DeviceError> {
+    device.useruuid = user_uuid.clone();
+    // Never write back a list that could not be read, that would drop every other device of the user.
+    let mut list = try_get_device_list(user_uuid)?;
+    // The device is stored before it is listed, so the list never points at a device that doesn't exist.
+    let value = serde_json::to_value(&device).unwrap();
+    if is_dry_run() {
+        info!("[dry-run] Adding device {} : {}", device.guid, value);
+    } else {
+        let response = firebase_devices()
+            .at(&device.guid)
+            .map_err(network)?
+            .set(value)
+            .map_err(network)?;
+        if response.code != StatusCode::OK {
+            return Err(DeviceError::Firebase(response.code));
+        }
+    }
+    if list.contains(&device.guid) {
+        return Ok(());
+    }
+    list.push(device.guid.clone());
+    if !set_device_list(user_uuid, list) {
+        return Err(DeviceError::Network(format!("Could not update the devices of {}", user_uuid)));
+    }
+    Ok(())
+}
+
+/// Removes a device from the database, and from the device list of the given user. The device is only read from
+/// firebase, it isn't asked for its state first.
+/// # Params
+/// user_uuid: The user to remove the device of.
+/// device_guid: The device we want to remove.
+/// # Example
+/// ```
+/// use aa_models::device;
+/// use aa_models::device::{remove_device};
+///
+/// let uuid = String::from("eoqBGbi9AHUxtOrL3xpJZDQotGP2");
+/// let device_guid = String::from("test-in-testing");
+/// println!("{:?}", remove_device(&uuid,&device_guid));
+/// ```
+/// # Return
+/// Ok if the device was removed from both the device list and firebase, `DeviceError::NotFound` if the device isn't
+/// stored or isn't in the device list of the user, the error of `try_get_device_list` if the list could not be read,
+/// `DeviceError::Firebase` if firebase refused a write, or `DeviceError::Network` if firebase could not be reached.
+pub fn remove_device(user_uuid: &String, device_guid: &String) -> Result<(), DeviceError> {
+    let device = read_stored_device(device_guid)?;
+
+    let mut list = try_get_device_list(user_uuid)?;
+    let index = list
+        .iter()
+        .position(|guid| *guid == device.guid)
+        .ok_or_else(|| DeviceError::NotFound(format!("{} in the devices of {}", device_guid, user_uuid)))?;
+    debug!("[delete] Device index is {}", index);
+    list.remove(index);
+    // The device is unlisted before it is removed, so the list never points at a device that doesn't exist.
+    if !set_device_list(user_uuid, list) {
+        return Err(DeviceError::Network(format!("Could not update the devices of {}", user_uuid)));
+    }
+    if is_dry_run() {
+        info!("[dry-run] Removing device {}", device_guid);
+        return Ok(());
+    }
+    let response = firebase_devices()
+        .at(device_guid)
+        .map_err(network)?
+        .remove()
+        .map_err(network)?;
+    if response.code != StatusCode::OK {
+        return Err(DeviceError::Firebase(response.code));
+    }
+    Ok(())
+}
+
+/// The changes `apply_declarative` made to the devices of a user.
+#[derive(Debug, PartialEq, Eq, Serialize, Deserialize, Default, Clone)]
+pub struct ApplyReport {
+    /// The guids of the devices that were added.
+    pub created: Vec<String>,
+    /// The guids of the devices that were changed.
+    pub updated: Vec<String>,
+    /// The guids of the devices that were removed.
+    pub deleted: Vec<String>,
+}
+
+/// The document `export_declarative` and `apply_declarative` work with.
+#[derive(Serialize, Deserialize)]
+struct DeclarativeDoc {
+    devices: Vec<Device>,
+}
+
+/// Exports every device stored for the user as a human editable JSON document, which can be changed and given back to
+/// `apply_declarative`. Devices are sorted by guid so that the document stays stable between exports. Zones of
+/// SQLSprinkler hosts aren't stored in firebase, so they aren't exported.
+/// # Example
+///```
+/// use aa_models::device;
+///
+/// let doc = device::export_declarative(&String::from("eoqBGbi9AHUxtOrL3xpJZDQotGP2")).unwrap();
+/// println!("{}",doc);
+/// ```
+/// # Return
+/// * The pretty printed document, or the error of the first device that could not be read. A partial document is
+/// never made, as applying it would remove the missing devices.
+pub fn export_declarative(user_uuid: &String) -> Result<String, DeviceError> {
+    let doc = DeclarativeDoc {
+        devices: stored_devices(user_uuid)?,
+    };
+    Ok(serde_json::to_string_pretty(&doc).unwrap())
+}
+
+/// Makes the devices of the user match the given declarative document: devices that are only in the document are
+/// added, devices that differ are updated, and devices that are not in the document are removed. The state of
+/// existing devices is left alone, so applying an old document doesn't turn things on or off.
+/// # Example
+///```
+/// use aa_models::device;
+///
+/// let uuid = String::from("eoqBGbi9AHUxtOrL3xpJZDQotGP2");
+/// let doc = device::export_declarative(&uuid).unwrap();
+/// // Applying an unchanged document does nothing.
+/// let report = device::apply_declarative(&uuid, &doc).unwrap();
+/// assert!(report.created.is_empty() && report.updated.is_empty() && report.deleted.is_empty());
+/// ```
+/// # Params
+/// * `user_uuid` The user whose devices should match the document.
+/// * `doc` A document, as made by `export_declarative`.
+/// # Return
+/// * What was changed, or an error if the document could not be read or firebase refused a change.
+pub fn apply_declarative(user_uuid: &String, doc: &str) -> Result<ApplyReport, DeviceError> {
+    let desired: DeclarativeDoc =
+        serde_json::from_str(doc).map_err(|e| DeviceError::Deserialize(e.to_string()))?;
+    let current = stored_devices(user_uuid)?;
+    let desired_guids: HashSet<String> = desired
+        .devices
+        .iter()
+        .map(|device| device.guid.clone())
+        .collect();
+    let mut report = ApplyReport::default();
+
+    for mut device in desired.devices {
+        match current.iter().find(|existing| existing.guid == device.guid) {
+            Some(existing) => {
+                device.last_state = existing.last_state.clone();
+                device.useruuid = existing.useruuid.clone();
+                device.last_seen = existing.last_seen;
+                if device != *existing {
+                    device.database_update()?;
+                    report.updated.push(device.guid);
+                }
+            }
+            None => {
+                let guid = device.guid.clone();
+                add_device(user_uuid, device)?;
+                report.created.push(guid);
+            }
+        }
+    }
+
+    for device in current {
+        if desired_guids.contains(&device.guid) {
+            continue;
+        }
+        remove_device(user_uuid, &device.guid)?;
+        report.deleted.push(device.guid);
+    }
+    Ok(report)
+}
+
+/// Gets every device of the user as it is stored in firebase, sorted by guid. The devices aren't asked for their
+/// state, and nothing is written back.
+/// # Return
+/// The devices, the error of `try_get_device_list` if the device list of the user could not be read, or the error of
+/// the first device that could not be read.
+fn stored_devices(user_uuid: &String) -> Result<Vec<Device>, DeviceError> {
+    let mut devices = try_get_device_list(user_uuid)?
+        .iter()
+        .filter(|guid| !is_zone_guid(guid))
+        .map(read_stored_device)
+        .collect::<Result<Vec<Device>, DeviceError>>()?;
+    devices.sort_by(|a, b| a.guid.cmp(&b.guid));
+    Ok(devices)
+}
+
+/// Finds any guids that show up more than once in the devices of the given user, including the zones of their
+/// SQLSprinkler hosts.
+/// # Example
+///```
+/// use aa_models::device;
+///
+/// let duplicates = device::find_duplicate_guids(&String::from("eoqBGbi9AHUxtOrL3xpJZDQotGP2"));
+/// println!("{:?}",duplicates);
+/// ```
+/// # Return
+/// * Every guid that appears more than once, in the order they first appear.
+pub fn find_duplicate_guids(user_uuid: &String) -> Vec<String> {
+    duplicate_guids(&get_devices_uuid(user_uuid))
+}
+
+/// Finds the devices that are attached to more than one of the given users, which get commands from each of them.
+/// # Example
+///```
+/// use aa_models::device;
+///
+/// let users = vec![String::from("eoqBGbi9AHUxtOrL3xpJZDQotGP2"), String::from("test_user")];
+/// let duplicates = device::find_duplicate_guids_across_users(&users);
+/// println!("{:?}",duplicates);
+/// ```
+/// # Return
+/// * A map of the guids used by more than one user to those users, see `guids_shared_between_users`.
+pub fn find_duplicate_guids_across_users(users: &[String]) -> HashMap<String, Vec<String>> {
+    let devices_by_user: Vec<(String, Vec<Device>)> =
+        users.iter().map(|user| (user.clone(), get_devices_uuid(user))).collect();
+    guids_shared_between_users(&devices_by_user)
+}
+
+/// Finds the guids that show up in the devices of more than one of the given users. Only exact guids count, so the
+/// zones of a SQLSprinkler host (which start with the guid of the host) aren't duplicates of the host, and devices
+/// without a guid are ignored.
+/// # Example
+///```
+/// use aa_models::device::{self, Device};
+///
+/// let mut host = Device::default();
+/// host.guid = String::from("0b5a2b5c-aabb-4f5e-9c3d-1234567890ab");
+/// let mut zone = Device::default();
+/// zone.guid = String::from("0b5a2b5c-aabb-4f5e-9c3d-1234567890ab-1");
+/// let devices_by_user = vec![
+///     (String::from("alice"), vec![host.clone()]),
+///     (String::from("bob"), vec![host.clone(), zone]),
+///     (String::from("carol"), vec![host.clone(), host.clone()]),
+/// ];
+/// let shared = device::guids_shared_between_users(&devices_by_user);
+/// assert_eq!(1, shared.len());
+/// assert_eq!(vec!["alice", "bob", "carol"], shared[&host.guid]);
+/// ```
+/// # Return
+/// * A map of the guids used by more than one user to those users, in the order given.
+pub fn guids_shared_between_users(devices_by_user: &[(String, Vec<Device>)]) -> HashMap<String, Vec<String>> {
+    let mut users_by_guid: HashMap<String, Vec<String>> = HashMap::new();
+    for (user, devices) in devices_by_user {
+        for device in devices.iter().filter(|device| !device.guid.is_empty()) {
+            let users = users_by_guid.entry(device.guid.clone()).or_default();
+            if !users.contains(user) {
+                users.push(user.clone());
+            }
+        }
+    }
+    users_by_guid.retain(|_, users| users.len() > 1);
+    users_by_guid
+}
+
+/// Gets every google trait used by at least one of the devices of the given user.
+/// # Example
+///```
+/// use aa_models::device;
+///
+/// let traits = device::user_trait_coverage(&String::from("eoqBGbi9AHUxtOrL3xpJZDQotGP2"));
+/// println!("{:?}",traits);
+/// ```
+/// # Return
+/// * The set of traits, ie `action.devices.traits.OnOff`
+pub fn user_trait_coverage(user_uuid: &String) -> HashSet<String> {
+    get_devices_uuid(user_uuid)
+        .iter()
+        .flat_map(|device| device.get_google_device_traits())
+        .map(|device_trait| device_trait.to_string())
+        .collect()
+}
+
+/// Builds a full response to the SYNC intent of Google Home for the given devices.
+/// Please see:
+/// https://developers.google.com/assistant/smarthome/reference/intent/sync
+/// for more information on how this JSON looks like.
+/// # Example
+///```
+/// use aa_models::device::{build_sync_response, DeviceBuilder, DeviceType};
+///
+/// let devices = vec![DeviceBuilder::new().guid("test_light").kind(DeviceType::LIGHT).build().unwrap()];
+/// let response = build_sync_response("ff36a3cc", "eoqBGbi9AHUxtOrL3xpJZDQotGP2", &devices);
+/// assert_eq!("ff36a3cc", response["requestId"]);
+/// assert_eq!("eoqBGbi9AHUxtOrL3xpJZDQotGP2", response["payload"]["agentUserId"]);
+/// assert_eq!("test_light", response["payload"]["devices"][0]["id"]);
+/// ```
+/// # Params
+/// * `request_id` The id of the SYNC request being answered.
+/// * `agent_user_id` The user the devices belong to.
+/// * `devices` The devices of the user.
+pub fn build_sync_response(request_id: &str, agent_user_id: &str, devices: &[Device]) -> Value {
+    // SYNC is the one time TV's are asked what they can do, every later trait lookup uses what they answered.
+    #[cfg(feature = "tv")]
+    for device in devices.iter().filter(|device| device.kind == DeviceType::TV) {
+        tv::discover_capabilities(device);
+    }
+    let devices: Vec<Value> = devices
+        .iter()
+        .map(|device| device.google_smarthome_json())
+        .collect();
+    json!({
+        "requestId": request_id,
+        "payload": {
+            "agentUserId": agent_user_id,
+            "devices": devices
+        }
+    })
+}
+
+/// Builds a full response to the QUERY intent of Google Home for the given devices, with whether or not each device is
+/// online taken from the given map instead of being checked here, so the devices can be pinged beforehand (ie with
+/// `check_online`). Please see:
+/// https://developers.google.com/assistant/smarthome/reference/intent/query
+/// for more information on how this JSON looks like.
+/// # Example
+///```
+/// use std::collections::HashMap;
+/// use aa_models::device::{build_query_response, DeviceBuilder, DeviceType};
+/// use serde_json::json;
+///
+/// let devices = vec![
+///     DeviceBuilder::new().guid("test_switch").kind(DeviceType::SWITCH).last_state(json!(true)).build().unwrap(),
+///     DeviceBuilder::new().guid("test_outlet").kind(DeviceType::OUTLET).build().unwrap(),
+/// ];
+/// let mut online = HashMap::new();
+/// online.insert(String::from("test_switch"), true);
+/// online.insert(String::from("test_outlet"), false);
+/// let response = build_query_response("ff36a3cc", &devices, &online);
+/// let states = &response["payload"]["devices"];
+/// assert_eq!(json!({"on": true, "online": true, "status": "SUCCESS"}), states["test_switch"]);
+/// assert_eq!("OFFLINE", states["test_outlet"]["status"]);
+/// ```
+/// # Params
+/// * `request_id` The id of the QUERY request being answered.
+/// * `devices` The devices google asked about.
+/// * `online` Whether or not each device is online, by guid. Devices that aren't in the map are reported without an
+/// `online` state.
+pub fn build_query_response(request_id: &str, devices: &[Device], online: &HashMap<String, bool>) -> Value {
+    let states: serde_json::Map<String, Value> = devices
+        .iter()
+        .map(|device| {
+            let mut state = device.google_query_json();
+            match online.get(&device.guid) {
+                Some(true) => {
+                    state["online"] = Value::from(true);
+                    state["status"] = Value::from("SUCCESS");
+                }
+                Some(false) => {
+                    state["online"] = Value::from(false);
+                    state["status"] = Value::from("OFFLINE");
+                }
+                None => state["status"] = Value::from("SUCCESS"),
+            }
+            (device.guid.clone(), state)
+        })
+        .collect();
+    json!({
+        "requestId": request_id,
+        "payload": {
+            "devices": states
+        }
+    })
+}
+
+/// Builds a ReportState request for Google Home, reporting the current state of the given devices.
+/// Please see:
+/// https://developers.google.com/assistant/smarthome/develop/report-state
+/// for more information on how this JSON looks like.
+/// # Example
+///```
+/// use aa_models::device::{build_report_state, DeviceBuilder, DeviceType};
+/// use serde_json::json;
+///
+/// let devices = vec![DeviceBuilder::new()
+///     .guid("test_switch")
+///     .kind(DeviceType::SWITCH)
+///     .last_state(json!(true))
+///     .build()
+///     .unwrap()];
+/// let request = build_report_state("ff36a3cc", "eoqBGbi9AHUxtOrL3xpJZDQotGP2", &devices);
+/// assert_eq!("ff36a3cc", request["requestId"]);
+/// assert_eq!("eoqBGbi9AHUxtOrL3xpJZDQotGP2", request["agentUserId"]);
+/// assert_eq!(true, request["payload"]["devices"]["states"]["test_switch"]["on"]);
 /// ```
+/// # Params
+/// * `request_id` The id of this request.
+/// * `agent_user_id` The user the devices belong to.
+/// * `devices` The devices to report the state of.
+pub fn build_report_state(request_id: &str, agent_user_id: &str, devices: &[Device]) -> Value {
+    let states: serde_json::Map<String, Value> = devices
+        .iter()
+        .map(|device| (device.guid.clone(), device.google_query_json()))
+        .collect();
+    json!({
+        "requestId": request_id,
+        "agentUserId": agent_user_id,
+        "payload": {
+            "devices": {
+                "states": states
+            }
+        }
+    })
+}
+
+/// How many users `sweep_all_users` fetches at the same time.
+const SWEEP_CONCURRENCY: usize = 8;
+
+/// Fetches the devices of every given user, a few users at a time. Each user is fetched on their own thread, so a
+/// user whose devices can't be read (or that makes the fetch panic) only fails their own result instead of the whole
+/// sweep.
+/// # Example
+///```
 /// use aa_models::device;
-/// // Get a test device
-/// let device = device::get_device_from_guid(&String::from("test_switch"));
-/// //...
+///
+/// let users = vec![String::from("eoqBGbi9AHUxtOrL3xpJZDQotGP2")];
+/// for (user, devices) in device::sweep_all_users(&users) {
+///     match devices {
+///         Ok(devices) => println!("{}: {} devices", user, devices.len()),
+///         Err(e) => println!("{}: {}", user, e),
+///     }
+/// }
 /// ```
 /// # Params
-/// * `guid`  The GUID of the device we want to get.
+/// * `uuids` The users to fetch the devices of.
 /// # Return
-/// * A device that corresponds to the given uuid, if there is no match, return a default device.
-pub fn get_device_from_guid(guid: &String) -> Device {
-    if check_if_zone(guid) {
-        return get_zone(guid);
+/// * Each user along with their devices, or the error that happened while fetching them, in the order given.
+pub fn sweep_all_users(uuids: &[String]) -> Vec<(String, Result<Vec<Device>, DeviceError>)> {
+    let mut results = Vec::with_capacity(uuids.len());
+    for chunk in uuids.chunks(SWEEP_CONCURRENCY) {
+        let handles: Vec<_> = chunk
+            .iter()
+            .map(|uuid| {
+                let uuid = uuid.clone();
+                thread::spawn(move || {
+                    let list = try_get_device_list(&uuid)?;
+                    Ok(device_list_from_firebase(serde_json::to_value(list).unwrap(), None, true))
+                })
+            })
+            .collect();
+        for (uuid, handle) in chunk.iter().zip(handles) {
+            let result = handle
+                .join()
+                .unwrap_or_else(|_| Err(DeviceError::Panicked(format!("fetching the devices of {}", uuid))));
+            results.push((uuid.clone(), result));
+        }
     }
+    results
+}
 
-    let device_value = get_firebase_devices().at(guid).unwrap().get().unwrap().body;
+/// How many devices `check_online` pings at the same time.
+const PING_CONCURRENCY: usize = 16;
 
-    let mut dev = match serde_json::from_value(device_value) {
-        Ok(d) => d,
-        Err(e) => {
-            debug!("Err: {}", e);
-            Device::default()
-        }
-    };
-    if dev == Device::default() {
-        debug!("Returning default device");
-        return dev;
-    }
-    match dev.kind {
-        DeviceType::SqlSprinklerHost => {
-            let ip = &dev.ip;
-            if dev.is_online() {
-                dev.last_state = Value::from(get_status_from_sqlsprinkler(ip).unwrap());
-                dev.database_update();
-            }
-        }
-        DeviceType::TV => {
-            dev = tv::parse_device(dev.clone());
-        }
-        DeviceType::BATTERY => {
-            dev = battery::parse_device(dev.clone());
+/// Checks whether or not each of the given devices is online, pinging a few devices at a time instead of one after
+/// another.
+/// # Example
+///```
+/// use aa_models::device;
+///
+/// let devices = device::get_devices_uuid(&String::from("eoqBGbi9AHUxtOrL3xpJZDQotGP2"));
+/// for (guid, online) in device::check_online(&devices) {
+///     println!("{}: {}", guid, online);
+/// }
+/// ```
+/// # Return
+/// * The guid of each device along with whether or not it is online, in the order given.
+pub fn check_online(devices: &[Device]) -> Vec<(String, bool)> {
+    let mut results = Vec::with_capacity(devices.len());
+    for chunk in devices.chunks(PING_CONCURRENCY) {
+        let handles: Vec<_> = chunk
+            .iter()
+            .map(|device| {
+                let device = device.clone();
+                thread::spawn(move || device.is_online())
+            })
+            .collect();
+        for (device, handle) in chunk.iter().zip(handles) {
+            results.push((device.guid.clone(), handle.join().unwrap_or(false)));
         }
-        _ => {}
     }
-    dev
+    results
 }
 
-/// Gets all of the devices that are connected to this user in the database.
-///
+/// How long a device may go unseen before its stored state is counted as stale by `user_device_health`.
+pub const STALE_STATE_AFTER: Duration = Duration::from_secs(60 * 60);
+
+/// How the devices of a user are doing, for a status banner.
+#[derive(Debug, PartialEq, Eq, Serialize, Deserialize, Default, Clone)]
+pub struct DeviceHealthReport {
+    /// How many devices answered a ping.
+    pub online: usize,
+    /// How many devices did not answer a ping.
+    pub offline: usize,
+    /// The guids of the UPS's that are running on battery.
+    pub ups_on_battery: Vec<String>,
+    /// The guids of the SQLSprinkler hosts that could not be reached.
+    pub unreachable_sprinkler_hosts: Vec<String>,
+    /// The guids of the devices that are offline and were not seen within `STALE_STATE_AFTER`, so their stored state
+    /// may be out of date.
+    pub stale: Vec<String>,
+}
+
+/// Checks how every device of the given user is doing, pinging the devices a few at a time. SQLSprinkler zones are
+/// covered by their host. The stored state of each device is used, so no device is asked for its state.
 /// # Example
 ///```
 /// use aa_models::device;
 ///
-/// let device_list = device::get_devices_uuid(&String::from("eoqBGbi9AHUxtOrL3xpJZDQotGP2"));
-/// println!("{:?}",device_list);
+/// let report = device::user_device_health(&String::from("eoqBGbi9AHUxtOrL3xpJZDQotGP2"));
+/// println!("{}", serde_json::to_string(&report).unwrap());
 /// ```
 /// # Return
-/// * A `Vec<Device>` containing all of the device information.
-pub fn get_devices_uuid(user_uuid: &String) -> Vec<Device> {
-    let firebase_device_list = get_device_list(user_uuid);
-    device_list_from_firebase(serde_json::to_value(firebase_device_list).unwrap())
+/// * The health report of the user's devices.
+pub fn user_device_health(user_uuid: &String) -> DeviceHealthReport {
+    let devices: Vec<Device> = get_device_list(user_uuid)
+        .iter()
+        .filter(|guid| !is_zone_guid(guid))
+        .map(get_stored_device_from_guid)
+        .filter(|device| *device != Device::default())
+        .collect();
+    let online = check_online(&devices);
+    build_health_report(&devices, &online)
 }
 
-/// Gets all the GUIDs belonging to the given user uuid.
+/// Builds the health report of the given devices, from whether or not each of them is online (as given by
+/// `check_online`).
 /// # Example
 ///```
-/// use aa_models::device;
+/// use aa_models::device::{build_health_report, DeviceBuilder, DeviceType};
 ///
-/// let device_list = device::get_device_list(&String::from("eoqBGbi9AHUxtOrL3xpJZDQotGP2"));
-/// println!("{:?}",device_list);
+/// let devices = vec![
+///     DeviceBuilder::new().guid("light_1").kind(DeviceType::LIGHT).build().unwrap(),
+///     DeviceBuilder::new().guid("host_1").kind(DeviceType::SqlSprinklerHost).build().unwrap(),
+/// ];
+/// let online = vec![(String::from("light_1"), true), (String::from("host_1"), false)];
+/// let report = build_health_report(&devices, &online);
+/// assert_eq!((1, 1), (report.online, report.offline));
+/// assert_eq!(vec!["host_1"], report.unreachable_sprinkler_hosts);
+/// // The host was never seen either.
+/// assert_eq!(vec!["host_1"], report.stale);
 /// ```
 /// # Return
-/// * A `Vec<Device>` containing all of the device information.
-pub fn get_device_list(user_uuid: &String) -> Vec<String> {
-    let firebase_device_list = get_firebase_users()
-        .at(&user_uuid)
-        .unwrap()
-        .at("devices")
-        .unwrap()
-        .get()
-        .unwrap()
-        .body;
-    let list: Vec<String> = match serde_json::from_value(firebase_device_list) {
-        Ok(r) => r,
-        Err(..) => vec![],
-    };
-    list
+/// * The health report of the devices. Devices missing from `online` are counted as offline.
+pub fn build_health_report(devices: &[Device], online: &[(String, bool)]) -> DeviceHealthReport {
+    let online: HashMap<&str, bool> = online.iter().map(|(guid, online)| (guid.as_str(), *online)).collect();
+    let stale_before = Utc::now() - chrono::Duration::from_std(STALE_STATE_AFTER).unwrap();
+    let mut report = DeviceHealthReport::default();
+    for device in devices {
+        #[cfg(feature = "battery")]
+        {
+            if device.ups_status().map_or(false, |status| status.on_battery) {
+                report.ups_on_battery.push(device.guid.clone());
+            }
+        }
+        if online.get(device.guid.as_str()).copied().unwrap_or(false) {
+            report.online += 1;
+            continue;
+        }
+        report.offline += 1;
+        if device.kind == DeviceType::SqlSprinklerHost {
+            report.unreachable_sprinkler_hosts.push(device.guid.clone());
+        }
+        if device.last_seen.map_or(true, |last_seen| last_seen < stale_before) {
+            report.stale.push(device.guid.clone());
+        }
+    }
+    report
 }
 
-/// Sets the list of devices for the user
-/// # Params
-/// user_uuid: The user uuid we want to update
-/// device_list: The list of device GUIDs.
+/// Gets the devices that are due to be polled, each at its own `poll_interval`.
 /// # Example
 ///```
-/// use aa_models::device;
-/// use aa_models::device::{get_device_list,set_device_list};
-/// // Create a copy of the current device list
-/// let backup_list = get_device_list(&String::from("eoqBGbi9AHUxtOrL3xpJZDQotGP2"));
-/// let mut list = get_device_list(&String::from("eoqBGbi9AHUxtOrL3xpJZDQotGP2"));
-/// list.push(String::from("8675309"));
-/// set_device_list(&String::from("eoqBGbi9AHUxtOrL3xpJZDQotGP2"),list.clone());
-/// assert_ne!(&backup_list, &list);
-/// set_device_list(&String::from("eoqBGbi9AHUxtOrL3xpJZDQotGP2"),backup_list.clone());
-/// list = get_device_list(&String::from("eoqBGbi9AHUxtOrL3xpJZDQotGP2"));
-/// assert_eq!(&backup_list,&list);
+/// use std::collections::HashMap;
+/// use std::time::Instant;
+/// use aa_models::device::{devices_due, DeviceBuilder};
+///
+/// let devices = vec![
+///     DeviceBuilder::new().guid("test_switch").build().unwrap(),
+///     DeviceBuilder::new().guid("test_light").build().unwrap(),
+/// ];
+/// let mut last_polled = HashMap::new();
+/// last_polled.insert(String::from("test_switch"), Instant::now());
+/// let due: Vec<&str> = devices_due(&devices, &last_polled).iter().map(|d| d.guid.as_str()).collect();
+/// // The light was never polled, the switch just was.
+/// assert_eq!(vec!["test_light"], due);
 /// ```
-pub fn set_device_list(user_uuid: &String, device_list: Vec<String>) -> bool {
-    get_firebase_users()
-        .at(&user_uuid)
-        .unwrap()
-        .at("devices")
-        .unwrap()
-        .set(serde_json::to_value(device_list).unwrap())
-        .is_ok()
+/// # Params
+/// * `devices` The devices to check.
+/// * `last_polled` When each device was last polled, by guid.
+/// # Return
+/// * The devices that were never polled or whose `poll_interval` went by, in the order given.
+pub fn devices_due<'a>(devices: &'a [Device], last_polled: &HashMap<String, Instant>) -> Vec<&'a Device> {
+    devices
+        .iter()
+        .filter(|device| last_polled.get(&device.guid).map_or(true, |polled| device.poll_due(*polled)))
+        .collect()
 }
 
-/// Adds a device to the given users account.
-/// # Params
-/// user_uuid: The user to add the device to.
-/// device: The device we want to add.
+/// Groups the given devices by their type, keeping the order they were given in within each group.
 /// # Example
 ///```
-/// use aa_models::device;
-/// use aa_models::device::{get_device_list, set_device_list, DeviceType, add_device};
+/// use aa_models::device::{group_by_type, DeviceBuilder, DeviceType};
 ///
-/// let uuid = String::from("eoqBGbi9AHUxtOrL3xpJZDQotGP2");
-/// let mut device = device::Device::default();
-/// device.guid = String::from("test-in-testing");
-/// device.kind = DeviceType::LIGHT;
-/// device.useruuid = uuid.clone();
-/// add_device(&uuid,device);
+/// let devices = vec![
+///     DeviceBuilder::new().guid("light_1").kind(DeviceType::LIGHT).build().unwrap(),
+///     DeviceBuilder::new().guid("switch_1").kind(DeviceType::SWITCH).build().unwrap(),
+///     DeviceBuilder::new().guid("light_2").kind(DeviceType::LIGHT).build().unwrap(),
+/// ];
+/// let groups = group_by_type(devices);
+/// let lights: Vec<&str> = groups[&DeviceType::LIGHT].iter().map(|d| d.guid.as_str()).collect();
+/// assert_eq!(vec!["light_1", "light_2"], lights);
+/// assert_eq!(1, groups[&DeviceType::SWITCH].len());
 /// ```
-pub fn add_device(user_uuid: &String, mut device: Device) {
-    device.useruuid = user_uuid.parse().unwrap();
-    let mut list = get_device_list(user_uuid);
-    list.push(device.guid.clone());
-    set_device_list(user_uuid, list);
-    get_firebase_devices()
-        .at(&device.guid)
-        .unwrap()
-        .set(serde_json::to_value(&device).unwrap())
-        .unwrap();
+/// # Return
+/// * A map of each device type to the devices of that type.
+pub fn group_by_type(devices: Vec<Device>) -> HashMap<DeviceType, Vec<Device>> {
+    let mut groups: HashMap<DeviceType, Vec<Device>> = HashMap::new();
+    for device in devices {
+        groups.entry(device.kind).or_insert_with(Vec::new).push(device);
+    }
+    groups
 }
 
-/// Removes a device from the database. The user id of the given device guid must match.
-/// If there is not a match, no action will be taken.
-/// # Params
-/// user_uuid: The user to remove the device of.
-/// device_guid: The device we want to remove.
+/// The orders `sort_devices` can sort devices in.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum SortKey {
+    /// By name, ignoring case. Devices without a name are sorted by their guid, see `Device::get_name`.
+    Name,
+    /// By type, then by name.
+    Type,
+    /// Online devices first, then by name. Every device is pinged, see `check_online`.
+    OnlineFirst,
+}
+
+/// Sorts the given devices in the given order. Devices that are equal in that order keep the order they were in.
 /// # Example
-/// ```
-/// use aa_models::device;
-/// use aa_models::device::{remove_device};
+///```
+/// use aa_models::device::{sort_devices, DeviceBuilder, DeviceType, SortKey};
+/// use serde_json::json;
 ///
-/// let uuid = String::from("eoqBGbi9AHUxtOrL3xpJZDQotGP2");
-/// let device_guid = String::from("test-in-testing");
-/// remove_device(&uuid,&device_guid);
+/// let mut devices = vec![
+///     DeviceBuilder::new().guid("porch").name("porch light").kind(DeviceType::LIGHT).build().unwrap(),
+///     DeviceBuilder::new().guid("fan").kind(DeviceType::SWITCH).build().unwrap(),
+///     DeviceBuilder::new().guid("lamp").name("Desk lamp").kind(DeviceType::LIGHT).build().unwrap(),
+/// ];
+/// sort_devices(&mut devices, SortKey::Name);
+/// let names: Vec<&str> = devices.iter().map(|d| d.get_name()).collect();
+/// assert_eq!(vec!["Desk lamp", "fan", "porch light"], names);
+///
+/// sort_devices(&mut devices, SortKey::Type);
+/// let guids: Vec<&str> = devices.iter().map(|d| d.guid.as_str()).collect();
+/// assert_eq!(vec!["lamp", "porch", "fan"], guids);
+///
+/// // Devices without an IP are offline when they have no state.
+/// devices[0].last_state = json!(null);
+/// devices[1].last_state = json!(null);
+/// sort_devices(&mut devices, SortKey::OnlineFirst);
+/// assert_eq!("fan", devices[0].guid);
 /// ```
-pub fn remove_device(user_uuid: &String, device_guid: &String) -> bool {
-    let device_from_guid = get_device_from_guid(device_guid);
-
-    if device_from_guid.guid != device_guid.clone() || device_from_guid == Device::default() {
-        return false;
+pub fn sort_devices(devices: &mut [Device], by: SortKey) {
+    match by {
+        SortKey::Name => devices.sort_by_cached_key(|device| device.get_name().to_lowercase()),
+        SortKey::Type => {
+            devices.sort_by_cached_key(|device| (device.kind.to_string(), device.get_name().to_lowercase()))
+        }
+        SortKey::OnlineFirst => {
+            let online: HashMap<String, bool> = check_online(devices).into_iter().collect();
+            devices.sort_by_cached_key(|device| {
+                let is_online = online.get(&device.guid).copied().unwrap_or(false);
+                (!is_online, device.get_name().to_lowercase())
+            })
+        }
     }
+}
 
-    let mut list = get_device_list(user_uuid);
-    let index = list
-        .iter()
-        .position(|x| *x == device_from_guid.guid)
-        .unwrap();
-    debug!("[delete] Device index is {}", index);
-    list.remove(index);
-    set_device_list(user_uuid, list);
-    get_firebase_devices()
-        .at(device_guid)
-        .unwrap()
-        .remove()
-        .unwrap()
-        .is_success()
+/// Gets every guid that appears more than once in the given devices.
+fn duplicate_guids(devices: &[Device]) -> Vec<String> {
+    let mut seen = HashSet::new();
+    let mut duplicates = vec![];
+    for device in devices {
+        if device.guid.is_empty() {
+            continue;
+        }
+        if !seen.insert(device.guid.as_str()) && !duplicates.contains(&device.guid) {
+            duplicates.push(device.guid.clone());
+        }
+    }
+    duplicates
 }
 
-/// Gets all the devices from firebase + any SQLSprinkler devices
-fn device_list_from_firebase(body: Value) -> Vec<Device> {
+/// Gets all the devices from firebase + any SQLSprinkler devices.
+/// # Params
+/// * `body` The list of device guids of the user.
+/// * `kinds` The device types to keep, or None to keep every device. The zones of a SQLSprinkler host are only
+/// fetched when `SPRINKLER` is kept.
+/// * `expand_zones` Whether or not to fetch the zones of SQLSprinkler hosts at all.
+///
+/// The stored kind of each device is read first, and only the devices that are kept are asked for their state.
+fn device_list_from_firebase(body: Value, kinds: Option<&[DeviceType]>, expand_zones: bool) -> Vec<Device> {
+    let wanted = |kind: &DeviceType| kinds.map_or(true, |kinds| kinds.contains(kind));
     let device_guid_list: Vec<String> = match serde_json::from_value(body) {
         Ok(res) => res,
         Err(..) => vec![String::from("")],
     };
-    let mut device_list = vec![];
-
-    // Get all the devices that belong to our user and store them in a list.
-    for guid in device_guid_list {
-        device_list.push(get_device_from_guid(&guid));
-    }
 
     let mut final_list = vec![];
 
-    for _dev in device_list.clone() {
-        let mut dev = _dev;
-
-        match dev.kind {
-            DeviceType::TV => {
-                dev = tv::parse_device(dev.clone());
-                final_list.push(dev);
+    for guid in device_guid_list {
+        if is_zone_guid(&guid) {
+            if wanted(&DeviceType::SPRINKLER) {
+                final_list.push(get_device_from_guid(&guid));
+            }
+            continue;
+        }
+        let stored = match read_stored_device(&guid) {
+            Ok(dev) => dev,
+            Err(e) => {
+                debug!("Could not get device {}: {}", guid, e);
+                Device::default()
             }
+        };
+        let lists_zones = cfg!(feature = "sqlsprinkler")
+            && stored.kind == DeviceType::SqlSprinklerHost
+            && expand_zones
+            && wanted(&DeviceType::SPRINKLER);
+        if !wanted(&stored.kind) && !lists_zones {
+            continue;
+        }
 
+        match stored.kind {
+            #[cfg(feature = "sqlsprinkler")]
             DeviceType::SqlSprinklerHost => {
-                // Only get the sprinkler system list if the device is online.
-                if dev.is_online() {
-                    let sprinkler_list = check_if_device_is_sqlsprinkler_host(dev.clone());
-                    for sprinkler in sprinkler_list {
-                        final_list.push(sprinkler);
-                    }
-                    final_list.push(dev.clone());
+                // Only list the host, and its zones, if it is online. It is pinged once, and its zones are fetched
+                // once: along with its state when it is kept, on their own when only its zones are listed.
+                if !stored.is_online() {
+                    continue;
+                }
+                let (dev, zones) = if wanted(&stored.kind) {
+                    refresh_online_host(stored)
                 } else {
-                    // Force device to show as being turned off.
-                    dev.last_state = serde_json::Value::Bool(false);
+                    let zones = get_zones_from_sqlsprinkler_with_timeout(&stored.ip, stored.request_timeout());
+                    (stored, zones)
+                };
+                if lists_zones {
+                    match zones {
+                        Ok(zones) => final_list.extend(zone_devices(&dev, zones)),
+                        Err(e) => warn!("Could not get the zones of {}: {}", dev.guid, e),
+                    }
+                }
+                if wanted(&dev.kind) {
+                    final_list.push(dev);
                 }
             }
             // Push everything else.
-            _ => {
-                final_list.push(dev.clone());
-            }
+            _ => final_list.push(refresh_from_hardware(stored)),
         }
     }
     final_list
 }
 
 impl fmt::Display for Device {
-    /// Pretty-prints this Device as a JSON object.
+    /// Pretty-prints this Device as a JSON object. The PIN and auth token are never printed.
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        let serialized = serde_json::to_string(&self).unwrap();
+        let mut serialized = serde_json::to_value(&self).unwrap();
+        if self.pin.is_some() {
+            serialized["pin"] = Value::from("***");
+        }
+        if self.auth_token.is_some() {
+            serialized["auth_token"] = Value::from("***");
+        }
         write!(f, "{}", serialized)
     }
 }
 
+#[cfg(feature = "sqlsprinkler")]
 impl From<Zone> for Device {
     /// Converts a SQLSprinkler zone to a Device.
     fn from(zone: Zone) -> Device {
+        Device::from(&zone)
+    }
+}
+
+#[cfg(feature = "sqlsprinkler")]
+impl From<&Zone> for Device {
+    /// Converts a SQLSprinkler zone to a Device, without taking the zone.
+    /// # Example
+    /// ```
+    /// use aa_models::device::{Device, DeviceType};
+    /// use aa_models::sqlsprinkler::Zone;
+    /// let zones = vec![Zone {
+    ///     name: String::from("Garden"),
+    ///     gpio: 4,
+    ///     time: 10,
+    ///     enabled: true,
+    ///     auto_off: true,
+    ///     system_order: 0,
+    ///     state: false,
+    ///     id: 3,
+    ///     time_remaining: None,
+    ///     faults: vec![],
+    /// }];
+    /// let devices: Vec<Device> = zones.iter().map(Device::from).collect();
+    /// assert_eq!(DeviceType::SPRINKLER, devices[0].kind);
+    /// assert_eq!("Garden", zones[0].name);
+    /// ```
+    fn from(zone: &Zone) -> Device {
         let zone_name = format!("Zone {}", &zone.system_order + 1);
         let pretty_name = format!("{}", &zone.name);
         let nicknames = vec![pretty_name, zone_name];
+        let remaining = zone.remaining().map_or(-1, |r| r.as_secs() as i64);
         Device {
             ip: "".to_string(),
             guid: zone.id.to_string(),
@@ -620,13 +2973,116 @@ impl From<Zone> for Device {
             last_state: json!({
                 "on": zone.state,
                 "id": zone.id,
-                "index": zone.system_order
+                "index": zone.system_order,
+                "timerRemainingSec": remaining,
+                "faults": zone.faults
             }),
             sw_version: zone.id.to_string(),
             useruuid: "".to_string(),
-            name: zone.name,
+            name: zone.name.clone(),
             nicknames,
+            reporting: None,
+            request_timeout_secs: None,
+            icon: None,
+            color: None,
+            model: None,
+            last_seen: None,
+            stale: false,
+            temperature_unit: TemperatureUnit::C,
+            pin: None,
+            auth_token: None,
+            https: false,
+            accept_self_signed_cert: false,
+            mac: None,
+            poll_interval_secs: None,
+            brightness_scale: BrightnessScale::default(),
+            relay_index: None,
+            start_stop: false,
+        }
+    }
+}
+
+/// Builds a `Device` without having to write out every field; anything that isn't set is left as in
+/// `Device::default()`.
+/// # Example
+/// ```
+/// use aa_models::device::{DeviceBuilder, DeviceType, HardwareType};
+/// let device = DeviceBuilder::new()
+///     .guid("test_light")
+///     .ip("127.0.0.1")
+///     .kind(DeviceType::LIGHT)
+///     .hardware(HardwareType::ARDUINO)
+///     .name("Test Light")
+///     .nicknames(vec![String::from("Lamp")])
+///     .build()
+///     .unwrap();
+/// assert_eq!("Test Light", device.get_name());
+/// assert_eq!(DeviceType::LIGHT, device.kind);
+///
+/// // Every device needs a guid.
+/// assert!(DeviceBuilder::new().name("No Guid").build().is_err());
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct DeviceBuilder {
+    device: Device,
+}
+
+impl DeviceBuilder {
+    /// Creates a builder for a default device.
+    pub fn new() -> DeviceBuilder {
+        DeviceBuilder::default()
+    }
+
+    /// Sets the IP of the device.
+    pub fn ip(mut self, ip: &str) -> DeviceBuilder {
+        self.device.ip = ip.to_string();
+        self
+    }
+
+    /// Sets the GUID of the device, this must be set.
+    pub fn guid(mut self, guid: &str) -> DeviceBuilder {
+        self.device.guid = guid.to_string();
+        self
+    }
+
+    /// Sets what kind of device this is.
+    pub fn kind(mut self, kind: DeviceType) -> DeviceBuilder {
+        self.device.kind = kind;
+        self
+    }
+
+    /// Sets the hardware used by the device.
+    pub fn hardware(mut self, hardware: HardwareType) -> DeviceBuilder {
+        self.device.hardware = hardware;
+        self
+    }
+
+    /// Sets the state of the device.
+    pub fn last_state(mut self, last_state: Value) -> DeviceBuilder {
+        self.device.last_state = last_state;
+        self
+    }
+
+    /// Sets the name of the device.
+    pub fn name(mut self, name: &str) -> DeviceBuilder {
+        self.device.name = name.to_string();
+        self
+    }
+
+    /// Sets the nicknames of the device.
+    pub fn nicknames(mut self, nicknames: Vec<String>) -> DeviceBuilder {
+        self.device.nicknames = nicknames;
+        self
+    }
+
+    /// Builds the device.
+    /// # Return
+    /// The device, or `DeviceError::MissingField` if no guid was set.
+    pub fn build(self) -> Result<Device, DeviceError> {
+        if self.device.guid.is_empty() {
+            return Err(DeviceError::MissingField(String::from("guid")));
         }
+        Ok(self.device)
     }
 }
 
@@ -642,6 +3098,23 @@ impl ::std::default::Default for Device {
             useruuid: "".to_string(),
             name: "".to_string(),
             nicknames: vec!["".to_string()],
+            reporting: None,
+            request_timeout_secs: None,
+            icon: None,
+            color: None,
+            model: None,
+            last_seen: None,
+            stale: false,
+            temperature_unit: TemperatureUnit::C,
+            pin: None,
+            auth_token: None,
+            https: false,
+            accept_self_signed_cert: false,
+            mac: None,
+            poll_interval_secs: None,
+            brightness_scale: BrightnessScale::default(),
+            relay_index: None,
+            start_stop: false,
         }
     }
 }
@@ -659,10 +3132,71 @@ impl Clone for Device {
             useruuid: self.useruuid.clone(),
             name: self.name.clone(),
             nicknames: self.nicknames.clone(),
+            reporting: self.reporting.clone(),
+            request_timeout_secs: self.request_timeout_secs,
+            icon: self.icon.clone(),
+            color: self.color.clone(),
+            model: self.model.clone(),
+            last_seen: self.last_seen,
+            stale: self.stale,
+            temperature_unit: self.temperature_unit,
+            pin: self.pin.clone(),
+            auth_token: self.auth_token.clone(),
+            https: self.https,
+            accept_self_signed_cert: self.accept_self_signed_cert,
+            mac: self.mac.clone(),
+            poll_interval_secs: self.poll_interval_secs,
+            brightness_scale: self.brightness_scale,
+            relay_index: self.relay_index,
+            start_stop: self.start_stop,
         }
     }
 }
 
+impl PartialEq for Device {
+    /// Compares every field of the two devices but `stale`, which is only known at runtime, so that a stored copy and
+    /// a live copy of the same device are equal.
+    /// # Example
+    /// ```
+    /// use aa_models::device::Device;
+    /// let stored = Device::default();
+    /// let mut live = stored.clone();
+    /// live.stale = true;
+    /// assert_eq!(stored, live);
+    /// live.name = String::from("Porch");
+    /// assert_ne!(stored, live);
+    /// ```
+    fn eq(&self, other: &Self) -> bool {
+        self.ip == other.ip
+            && self.guid == other.guid
+            && self.kind == other.kind
+            && self.hardware == other.hardware
+            && self.last_state == other.last_state
+            && self.sw_version == other.sw_version
+            && self.useruuid == other.useruuid
+            && self.name == other.name
+            && self.nicknames == other.nicknames
+            && self.reporting == other.reporting
+            && self.request_timeout_secs == other.request_timeout_secs
+            && self.icon == other.icon
+            && self.color == other.color
+            && self.model == other.model
+            && self.last_seen == other.last_seen
+            && self.temperature_unit == other.temperature_unit
+            && self.pin == other.pin
+            && self.auth_token == other.auth_token
+            && self.https == other.https
+            && self.accept_self_signed_cert == other.accept_self_signed_cert
+            && self.mac == other.mac
+            && self.poll_interval_secs == other.poll_interval_secs
+            && self.brightness_scale == other.brightness_scale
+            && self.relay_index == other.relay_index
+            && self.start_stop == other.start_stop
+    }
+}
+
+impl Eq for Device {}
+
 impl GoogleDevice for Device {
     /// Gets this device as a JSON value that can be used as an OnSync request for
     /// Google Home. Please see:
@@ -680,7 +3214,7 @@ impl GoogleDevice for Device {
     fn google_smarthome_json(&self) -> Value {
         let traits = self.get_google_device_traits();
         let device_type = self.get_google_device_type();
-        let hardware_model = self.get_google_device_hardware();
+        let hardware_model = self.model.as_deref().unwrap_or(self.get_google_device_hardware());
         let attributes = self.get_attributes();
         let json = serde_json::json!({
 
@@ -692,7 +3226,7 @@ impl GoogleDevice for Device {
                     self.get_name()
                 ],
                 "name":self.get_name(),
-                "nicknames": self.nicknames
+                "nicknames": self.get_nicknames()
             },
             "attributes": attributes,
             "deviceInfo": {
@@ -705,6 +3239,76 @@ impl GoogleDevice for Device {
         });
         json
     }
+
+    /// Gets the current state of this device as a JSON value that can be used in a QUERY response for Google Home.
+    /// Please see:
+    /// https://developers.google.com/assistant/smarthome/reference/intent/query
+    /// for more information on how this JSON looks like.
+    ///
+    /// # Example
+    ///
+    ///```
+    /// use aa_models::device::{Device, DeviceType, GoogleDevice};
+    /// use serde_json::json;
+    /// let mut device = Device::default();
+    /// device.kind = DeviceType::TV;
+    /// device.last_state = json!({"on": false, "muted": true, "volume": 10, "volumeMax": 100});
+    /// // A TV that is off is never reported as muted.
+    /// assert_eq!(false, device.google_query_json()["isMuted"]);
+    ///```
+    fn google_query_json(&self) -> Value {
+        let mut state = json!({});
+        match self.kind {
+            #[cfg(feature = "tv")]
+            DeviceType::TV => {
+                let tv_state: tv::TvState =
+                    serde_json::from_value(self.last_state.clone()).unwrap_or_default();
+                state["on"] = Value::from(tv_state.on);
+                state["currentVolume"] = Value::from(tv_state.volume);
+                // Don't report a stale mute state when the TV is off.
+                state["isMuted"] = Value::from(tv_state.on && tv_state.muted);
+                state["currentInput"] = Value::from(tv_state.currentInput);
+            }
+            DeviceType::GARAGE => {
+                state["openPercent"] = Value::from(if self.is_on() { 100 } else { 0 });
+            }
+            DeviceType::SENSOR => {
+                let data = capabilities::sensor_state_data(&self.last_state);
+                if !data.is_empty() {
+                    state["currentSensorStateData"] = Value::from(data);
+                }
+                if let Some(temperature) = self.temperature_celsius() {
+                    state["temperatureAmbientCelsius"] = Value::from(temperature);
+                    state["temperatureSetpointCelsius"] = Value::from(temperature);
+                }
+                if let Some(humidity) = self.last_state[capabilities::HUMIDITY].as_f64() {
+                    state["humidityAmbientPercent"] = Value::from(humidity.round() as i64);
+                }
+            }
+            #[cfg(feature = "battery")]
+            DeviceType::BATTERY => {
+                state = battery::energy_storage_state(&self.last_state);
+            }
+            _ => {
+                state["on"] = Value::from(self.is_on());
+                if self.supports(GoogleTrait::StartStop) {
+                    state["isRunning"] = Value::from(self.is_on());
+                    state["isPaused"] = Value::from(false);
+                }
+                if let Some(brightness) = self.brightness_percent() {
+                    state["brightness"] = Value::from(brightness);
+                }
+                if let Some(rgb) = self.get_color_spectrum_rgb() {
+                    state["color"] = json!({ "spectrumRgb": rgb });
+                }
+            }
+        }
+        let status_report = self.get_status_report();
+        if !status_report.is_empty() {
+            state["currentStatusReport"] = Value::from(status_report);
+        }
+        state
+    }
 }
 
 impl DeviceTrait for Device {}
@@ -729,18 +3333,63 @@ impl FromStr for DeviceType {
             "BATTERY" => Ok(DeviceType::BATTERY),
             "LIGHT" => Ok(DeviceType::LIGHT),
             "SWITCH" => Ok(DeviceType::SWITCH),
+            "OUTLET" => Ok(DeviceType::OUTLET),
             "GARAGE" => Ok(DeviceType::GARAGE),
             "SPRINKLER" => Ok(DeviceType::SPRINKLER),
             "ROUTER" => Ok(DeviceType::ROUTER),
             "SQLSPRINKLER_HOST" => Ok(DeviceType::SqlSprinklerHost),
             "TV" => Ok(DeviceType::TV),
+            "SENSOR" => Ok(DeviceType::SENSOR),
             _ => Err(()),
         }
     }
 }
 
+impl fmt::Display for DeviceType {
+    /// Prints this DeviceType as the same token `from_str` accepts.
+    ///
+    /// # Example
+    /// Every device type round trips through its string.
+    /// ```
+    /// use std::str::FromStr;
+    /// use aa_models::device::DeviceType;
+    /// let kinds = [
+    ///     DeviceType::BATTERY,
+    ///     DeviceType::LIGHT,
+    ///     DeviceType::SWITCH,
+    ///     DeviceType::OUTLET,
+    ///     DeviceType::GARAGE,
+    ///     DeviceType::SPRINKLER,
+    ///     DeviceType::ROUTER,
+    ///     DeviceType::SqlSprinklerHost,
+    ///     DeviceType::TV,
+    ///     DeviceType::SENSOR,
+    /// ];
+    /// for kind in kinds.iter() {
+    ///     assert_eq!(Ok(*kind), DeviceType::from_str(&kind.to_string()));
+    /// }
+    /// assert_eq!("SQLSPRINKLER_HOST", DeviceType::SqlSprinklerHost.to_string());
+    /// ```
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            DeviceType::BATTERY => "BATTERY",
+            DeviceType::LIGHT => "LIGHT",
+            DeviceType::SWITCH => "SWITCH",
+            DeviceType::OUTLET => "OUTLET",
+            DeviceType::GARAGE => "GARAGE",
+            DeviceType::SPRINKLER => "SPRINKLER",
+            DeviceType::ROUTER => "ROUTER",
+            DeviceType::SqlSprinklerHost => "SQLSPRINKLER_HOST",
+            DeviceType::TV => "TV",
+            DeviceType::SENSOR => "SENSOR",
+        };
+        write!(f, "{}", name)
+    }
+}
+
 pub trait GoogleDevice {
     fn google_smarthome_json(&self) -> Value;
+    fn google_query_json(&self) -> Value;
 }
 
 /// A group of traits that relate to individual devices. Each method here returns a vec to be compliance with how
@@ -772,4 +3421,125 @@ pub trait DeviceTrait {
     fn energy_storage() -> Vec<&'static str> {
         vec!["action.devices.traits.EnergyStorage"]
     }
+
+    /// Gets all the traits that belong to lights that can be dimmed
+    fn brightness() -> Vec<&'static str> {
+        vec!["action.devices.traits.Brightness"]
+    }
+
+    /// Gets all the traits that belong to lights that can change color
+    fn color_setting() -> Vec<&'static str> {
+        vec!["action.devices.traits.ColorSetting"]
+    }
+
+    /// Gets all the traits that belong to things with switchable inputs
+    fn input_selector() -> Vec<&'static str> {
+        vec!["action.devices.traits.InputSelector"]
+    }
+
+    /// Gets all the traits that belong to things that report faults
+    fn status_report() -> Vec<&'static str> {
+        vec!["action.devices.traits.StatusReport"]
+    }
+
+    /// Gets all the traits that belong to read-only sensors
+    fn sensor_state() -> Vec<&'static str> {
+        vec!["action.devices.traits.SensorState"]
+    }
+
+    /// Gets all the traits that belong to things that report a temperature
+    fn temperature_control() -> Vec<&'static str> {
+        vec!["action.devices.traits.TemperatureControl"]
+    }
+
+    /// Gets all the traits that belong to things that report a humidity
+    fn humidity_setting() -> Vec<&'static str> {
+        vec!["action.devices.traits.HumiditySetting"]
+    }
+
+    /// Gets all the traits that belong to things that run until they are stopped
+    fn start_stop() -> Vec<&'static str> {
+        vec!["action.devices.traits.StartStop"]
+    }
+}
+
+/// The google home traits devices can have.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum GoogleTrait {
+    OnOff,
+    OpenClose,
+    Reboot,
+    EnergyStorage,
+    Volume,
+    Brightness,
+    ColorSetting,
+    InputSelector,
+    StatusReport,
+    SensorState,
+    TemperatureControl,
+    HumiditySetting,
+    StartStop,
+}
+
+impl GoogleTrait {
+    /// Every trait devices can have.
+    pub const ALL: [GoogleTrait; 13] = [
+        GoogleTrait::OnOff,
+        GoogleTrait::OpenClose,
+        GoogleTrait::Reboot,
+        GoogleTrait::EnergyStorage,
+        GoogleTrait::Volume,
+        GoogleTrait::Brightness,
+        GoogleTrait::ColorSetting,
+        GoogleTrait::InputSelector,
+        GoogleTrait::StatusReport,
+        GoogleTrait::SensorState,
+        GoogleTrait::TemperatureControl,
+        GoogleTrait::HumiditySetting,
+        GoogleTrait::StartStop,
+    ];
+
+    /// Gets the attributes google requires in the SYNC response of devices with this trait.
+    pub fn required_attributes(&self) -> &'static [&'static str] {
+        match self {
+            GoogleTrait::Volume => &["volumeMaxLevel", "volumeCanMuteAndUnmute"],
+            GoogleTrait::ColorSetting => &["colorModel"],
+            GoogleTrait::InputSelector => &["availableInputs"],
+            GoogleTrait::EnergyStorage => &["queryOnlyEnergyStorage"],
+            GoogleTrait::SensorState => &["sensorStatesSupported"],
+            GoogleTrait::TemperatureControl => &["temperatureRange", "temperatureUnitForUX"],
+            GoogleTrait::OnOff
+            | GoogleTrait::OpenClose
+            | GoogleTrait::Reboot
+            | GoogleTrait::Brightness
+            | GoogleTrait::StatusReport
+            | GoogleTrait::HumiditySetting
+            | GoogleTrait::StartStop => &[],
+        }
+    }
+
+    /// Gets the name of this trait as google knows it, ie `action.devices.traits.OnOff`
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            GoogleTrait::OnOff => "action.devices.traits.OnOff",
+            GoogleTrait::OpenClose => "action.devices.traits.OpenClose",
+            GoogleTrait::Reboot => "action.devices.traits.Reboot",
+            GoogleTrait::EnergyStorage => "action.devices.traits.EnergyStorage",
+            GoogleTrait::Volume => "action.devices.traits.Volume",
+            GoogleTrait::Brightness => "action.devices.traits.Brightness",
+            GoogleTrait::ColorSetting => "action.devices.traits.ColorSetting",
+            GoogleTrait::InputSelector => "action.devices.traits.InputSelector",
+            GoogleTrait::StatusReport => "action.devices.traits.StatusReport",
+            GoogleTrait::SensorState => "action.devices.traits.SensorState",
+            GoogleTrait::TemperatureControl => "action.devices.traits.TemperatureControl",
+            GoogleTrait::HumiditySetting => "action.devices.traits.HumiditySetting",
+            GoogleTrait::StartStop => "action.devices.traits.StartStop",
+        }
+    }
+}
+
+impl fmt::Display for GoogleTrait {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
 }
@@ -0,0 +1,88 @@
+use std::error::Error;
+use std::fmt;
+
+use isahc::error::ErrorKind;
+use isahc::http::StatusCode;
+
+use crate::command::ChallengeType;
+
+/// Errors that can happen when reading or writing devices.
+#[derive(Debug)]
+pub enum DeviceError {
+    /// Firebase or the device could not be reached at all (bad url, connection refused, dropped connection...)
+    Network(String),
+
+    /// Firebase or the device did not answer in time.
+    Timeout(String),
+
+    /// Firebase was reached, but it did not answer with 200 OK.
+    Firebase(StatusCode),
+
+    /// Firebase (or a proxy in front of it) answered a read with an error or a body that isn't data, along with the
+    /// start of that body.
+    Backend(StatusCode, String),
+
+    /// The device answered with an error status, along with the error message it gave.
+    Http(StatusCode, String),
+
+    /// The given field does not exist on a device.
+    UnknownField(String),
+
+    /// The given field is required, but was not set.
+    MissingField(String),
+
+    /// The device can't do what was asked of it.
+    Unsupported(String),
+
+    /// There is no device with the given guid.
+    NotFound(String),
+
+    /// Data could not be read as a device (or a list of devices).
+    Deserialize(String),
+
+    /// The command needs the given secondary verification before it is run.
+    ChallengeNeeded(ChallengeType),
+
+    /// Something panicked while doing the given work.
+    Panicked(String),
+}
+
+impl fmt::Display for DeviceError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            DeviceError::Network(e) => write!(f, "Could not reach the server: {}", e),
+            DeviceError::Timeout(e) => write!(f, "Timed out waiting for the server: {}", e),
+            DeviceError::Firebase(code) => write!(f, "Firebase responded with {}", code),
+            DeviceError::Backend(code, excerpt) => write!(f, "Backend responded with {}: {}", code, excerpt),
+            DeviceError::Http(code, message) => write!(f, "Device responded with {}: {}", code, message),
+            DeviceError::UnknownField(field) => write!(f, "Devices have no field named {}", field),
+            DeviceError::MissingField(field) => write!(f, "Devices must have a {}", field),
+            DeviceError::Unsupported(action) => write!(f, "The device does not support {}", action),
+            DeviceError::NotFound(guid) => write!(f, "There is no device {}", guid),
+            DeviceError::Deserialize(e) => write!(f, "Could not read device: {}", e),
+            DeviceError::ChallengeNeeded(challenge) => write!(f, "The command needs {}", challenge.as_str()),
+            DeviceError::Panicked(work) => write!(f, "Panicked while {}", work),
+        }
+    }
+}
+
+impl Error for DeviceError {}
+
+/// Wraps any transport error into a `DeviceError::Network`.
+pub(crate) fn network<E: fmt::Debug>(e: E) -> DeviceError {
+    DeviceError::Network(format!("{:?}", e))
+}
+
+/// Wraps an error from sending a request into a `DeviceError::Timeout` if the request timed out, or a
+/// `DeviceError::Network` otherwise.
+pub(crate) fn http_error(e: isahc::Error) -> DeviceError {
+    match e.kind() {
+        ErrorKind::Timeout => DeviceError::Timeout(e.to_string()),
+        _ => network(e),
+    }
+}
+
+/// Wraps an error from reading JSON into a `DeviceError::Deserialize`.
+pub(crate) fn deserialize(e: serde_json::Error) -> DeviceError {
+    DeviceError::Deserialize(e.to_string())
+}
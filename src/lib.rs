@@ -1,4 +1,14 @@
+pub mod arduino;
+#[cfg(feature = "battery")]
 pub mod battery;
+pub mod capabilities;
+pub mod command;
+pub mod config;
 pub mod device;
+pub mod error;
+pub mod router;
+#[cfg(feature = "sqlsprinkler")]
 pub mod sqlsprinkler;
+pub mod transport;
+#[cfg(feature = "tv")]
 pub mod tv;
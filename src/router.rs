@@ -0,0 +1,59 @@
+use isahc::error::ErrorKind;
+use isahc::prelude::*;
+use isahc::config::SslOption;
+use isahc::Request;
+use log::{debug, info};
+
+use crate::config::is_dry_run;
+
+use crate::device::{Device, DeviceType};
+use crate::error::{http_error, network, DeviceError};
+
+/// The endpoint routers are rebooted through when no other endpoint is given.
+pub const DEFAULT_REBOOT_ENDPOINT: &str = "reboot";
+
+/// Reboots the given router by POSTing to the given endpoint on its IP address, over HTTPS if the router uses it and
+/// with its auth token and certificate options (see `Device::request_options`).
+/// # Params
+/// * `dev` The router to reboot.
+/// * `endpoint` The endpoint of the router that reboots it, ie `reboot`.
+/// # Return
+/// Ok if the router accepted the reboot or dry-run mode is on, `DeviceError::Unsupported` if the device isn't a router,
+/// `DeviceError::Http` if the router refused, or `DeviceError::Network` if it could not be reached.
+pub fn reboot(dev: &Device, endpoint: &str) -> Result<(), DeviceError> {
+    if dev.kind != DeviceType::ROUTER {
+        return Err(DeviceError::Unsupported(String::from("Reboot")));
+    }
+    let scheme = if dev.uses_https() { "https" } else { "http" };
+    let url = format!("{}://{}/{}", scheme, dev.ip, endpoint.trim_start_matches('/'));
+    if is_dry_run() {
+        info!("[dry-run] POST {}", url);
+        return Ok(());
+    }
+    debug!("Rebooting {} with {}", dev.guid, url);
+    let options = dev.request_options();
+    let mut request = Request::post(&url).timeout(dev.request_timeout());
+    if options.accept_self_signed_cert {
+        request =
+            request.ssl_options(SslOption::DANGER_ACCEPT_INVALID_CERTS | SslOption::DANGER_ACCEPT_INVALID_HOSTS);
+    }
+    if let Some(token) = &options.auth_token {
+        request = request.header("Authorization", token.header_value());
+    }
+    let response = request.body(()).map_err(network)?.send();
+    match response {
+        Ok(mut res) if !res.status().is_success() => {
+            let body = res.text().unwrap_or_default();
+            Err(DeviceError::Http(res.status(), body.trim().to_string()))
+        }
+        Ok(..) => Ok(()),
+        // Routers often go down before they finish answering, which means the reboot started.
+        Err(e) if dropped_mid_reboot(e.kind()) => Ok(()),
+        Err(e) => Err(http_error(e)),
+    }
+}
+
+/// Checks whether or not the given error means the router was reached, but dropped the connection before answering.
+fn dropped_mid_reboot(kind: &ErrorKind) -> bool {
+    matches!(kind, ErrorKind::Io | ErrorKind::ProtocolViolation)
+}
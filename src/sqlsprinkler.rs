@@ -1,15 +1,23 @@
-use std::error::Error;
+use std::collections::HashMap;
+use std::time::Duration;
 
-use isahc::prelude::*;
-use isahc::Request;
-use log::debug;
+use isahc::http::StatusCode;
+use log::{debug, info, warn};
+use once_cell::sync::Lazy;
 use regex::Regex;
 use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
 
-use crate::device::{Device, DeviceType, get_device_from_guid};
+use crate::device::{
+    get_device_list, read_stored_device, set_device_list, try_get_device_list, Device, DeviceType,
+    DEFAULT_REQUEST_TIMEOUT,
+};
+use crate::config::is_dry_run;
+use crate::error::{deserialize, DeviceError};
+use crate::transport::{HttpTransport, IsahcTransport, RequestOptions};
 
 /// A struct representing the data from SQLSprinkler zones.
-#[derive(Deserialize)]
+#[derive(Serialize, Deserialize)]
 pub struct Zone {
     pub name: String,
     pub gpio: u8,
@@ -19,13 +27,114 @@ pub struct Zone {
     pub system_order: i8,
     pub state: bool,
     pub id: i8,
+    /// How many seconds are left on the zone's run, only reported by the host while the zone is on.
+    #[serde(default)]
+    pub time_remaining: Option<u64>,
+    /// Any faults the host detected on this zone (stuck valve, sensor fault...)
+    #[serde(default)]
+    pub faults: Vec<ZoneFault>,
+}
+
+/// A fault the SQLSprinkler host reported for a zone.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct ZoneFault {
+    /// The status code of the fault, given as a google status code (ie `deviceJammingDetected`)
+    pub code: String,
+    /// Whether or not the fault stops the zone from running. Non-blocking faults are reported as exceptions.
+    pub blocking: bool,
+}
+
+impl Zone {
+    /// Gets how much longer this zone will run for.
+    /// # Return
+    /// The time left on the zone's run, or None if the zone is not running.
+    pub fn remaining(&self) -> Option<Duration> {
+        if !self.state {
+            return None;
+        }
+        self.time_remaining.map(Duration::from_secs)
+    }
 }
 
 /// Represents data for toggling a zone.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct ZoneToggle {
+    /// The id of the zone.
+    pub id: i64,
+    /// Whether the zone should be on or off.
+    pub state: bool,
+}
+
+/// Represents data for changing how long a zone runs for.
 #[derive(Serialize, Deserialize, Debug)]
-struct ZoneToggle {
+struct ZoneTime {
     id: i64,
-    state: bool,
+    time: u64,
+}
+
+/// Represents data for enabling or disabling a zone.
+#[derive(Serialize, Deserialize, Debug)]
+struct ZoneEnabled {
+    id: i64,
+    enabled: bool,
+}
+
+/// Represents data for the rain delay of the system, in hours.
+#[derive(Serialize, Deserialize, Debug)]
+struct RainDelay {
+    hours: u32,
+}
+
+/// How the requests that change something on a SQLSprinkler host are sent.
+/// # Example
+///```
+/// use std::time::Duration;
+/// use aa_models::device::Device;
+/// use aa_models::sqlsprinkler::HostRequest;
+///
+/// let mut host = Device::default();
+/// host.request_timeout_secs = Some(10);
+/// host.https = true;
+/// let request = HostRequest::for_device(&host);
+/// assert_eq!(Duration::from_secs(10), request.timeout);
+/// assert!(request.https);
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HostRequest {
+    /// How long to wait for the host to answer.
+    pub timeout: Duration,
+    /// Whether or not the host is talked to over HTTPS.
+    pub https: bool,
+    /// The auth token and certificate options the request is sent with.
+    pub options: RequestOptions,
+}
+
+impl ::std::default::Default for HostRequest {
+    fn default() -> Self {
+        HostRequest {
+            timeout: DEFAULT_REQUEST_TIMEOUT,
+            https: false,
+            options: RequestOptions::default(),
+        }
+    }
+}
+
+impl HostRequest {
+    /// Gets how requests are sent to the given SQLSprinkler host (or one of its zones), see `Device::request_timeout`,
+    /// `Device::uses_https` and `Device::request_options`.
+    pub fn for_device(dev: &Device) -> HostRequest {
+        HostRequest {
+            timeout: dev.request_timeout(),
+            https: dev.uses_https(),
+            options: dev.request_options(),
+        }
+    }
+
+    /// Gets the url of the given endpoint of the host at the given IP address.
+    fn url(&self, ip: &str, endpoint: &str) -> String {
+        let scheme = if self.https { "https" } else { "http" };
+        format!("{}://{}:3030/{}", scheme, ip, endpoint)
+    }
 }
 
 /// Represents data for
@@ -35,85 +144,432 @@ struct SystemToggle {
 }
 
 /// Sets the zone status to the given state
+/// # Return
+/// True if the host accepted the change, see `try_set_zone` for why it did not.
 pub fn set_zone(ip: String, state: bool, id: i64) -> bool {
-    let url = format!("http://{}:3030/zone", ip);
-
-    let zone_toggle = ZoneToggle { id, state };
+    set_zone_with_transport(&IsahcTransport, ip, state, id)
+}
 
-    let send_res = match Request::put(&url)
-        .header("content-type", "application/json")
-        .body(serde_json::to_vec(&zone_toggle).unwrap())
-        .timeout(std::time::Duration::from_secs(3))
-        .unwrap()
-        .send()
-    {
-        Ok(res) => res.status().is_success(),
+/// Sets the zone status to the given state, sending the request through the given transport.
+/// # Return
+/// True if the host accepted the change, see `try_set_zone` for why it did not.
+pub fn set_zone_with_transport(transport: &dyn HttpTransport, ip: String, state: bool, id: i64) -> bool {
+    match try_set_zone_with_transport(transport, ip, state, id, &HostRequest::default()) {
+        Ok(..) => true,
         Err(e) => {
-            debug!("Error: {} with URL {}", e, &url);
+            debug!("Error: {}", e);
             false
         }
-    };
-    send_res
+    }
+}
+
+/// Sets the zone status to the given state, telling apart why it failed: a host that refuses the zone (any 4xx) has no
+/// such zone, while a host that can't be reached or doesn't answer in time is offline.
+/// # Params
+/// * `ip` The IP Address of the SQLSprinkler host.
+/// * `state` Whether the zone should be on or off.
+/// * `id` The id of the zone.
+/// # Return
+/// Ok if the host accepted the change, `DeviceError::NotFound` if the host doesn't know the zone,
+/// `DeviceError::Timeout` or `DeviceError::Network` if the host is offline, or `DeviceError::Http` with the error
+/// message of the host if it failed otherwise.
+pub fn try_set_zone(ip: String, state: bool, id: i64) -> Result<(), DeviceError> {
+    try_set_zone_with_transport(&IsahcTransport, ip, state, id, &HostRequest::default())
+}
+
+/// Sets the zone status to the given state, sending the request through the given transport as the given request
+/// says (ie with the timeout of the host, see `HostRequest::for_device`). See `try_set_zone`.
+/// # Example
+///```
+/// use std::time::Duration;
+/// use isahc::http::StatusCode;
+/// use aa_models::error::DeviceError;
+/// use aa_models::sqlsprinkler::{try_set_zone_with_transport, HostRequest};
+/// use aa_models::transport::{HttpResponse, HttpTransport};
+///
+/// struct NoSuchZone;
+///
+/// impl HttpTransport for NoSuchZone {
+///     fn get(&self, _url: &str, _timeout: Duration) -> Result<HttpResponse, DeviceError> {
+///         unreachable!()
+///     }
+///
+///     fn put_json(&self, _url: &str, _body: Vec<u8>, _timeout: Duration) -> Result<HttpResponse, DeviceError> {
+///         Ok(HttpResponse { status: StatusCode::BAD_REQUEST, body: String::from(r#"{"error": "no zone 9"}"#) })
+///     }
+/// }
+///
+/// let result = try_set_zone_with_transport(&NoSuchZone, String::from("10.0.0.2"), true, 9, &HostRequest::default());
+/// assert!(matches!(result, Err(DeviceError::NotFound(_))));
+/// ```
+pub fn try_set_zone_with_transport(
+    transport: &dyn HttpTransport,
+    ip: String,
+    state: bool,
+    id: i64,
+    request: &HostRequest,
+) -> Result<(), DeviceError> {
+    let url = request.url(&ip, "zone");
+
+    let zone_toggle = ZoneToggle { id, state };
+
+    match put_to_host(transport, &url, serde_json::to_vec(&zone_toggle).unwrap(), request) {
+        Err(DeviceError::Http(status, message)) if status.is_client_error() => {
+            debug!("{} refused zone {}: {}", ip, id, message);
+            Err(DeviceError::NotFound(format!("zone {} on {}", id, ip)))
+        }
+        result => result,
+    }
+}
+
+/// Stops the given zone right away. There is no timer registry on this side: the auto-off timer of a zone lives on
+/// the host, which cancels it when the zone is turned off. This reads the zone back to make sure it actually stopped
+/// and has no time left on its run, except in dry-run mode where nothing was sent.
+/// # Params
+/// * `ip` The IP Address of the SQLSprinkler host.
+/// * `id` The id of the zone.
+/// # Return
+/// Ok if the zone is stopped, the error from the host if it refused, or `DeviceError::Http` with
+/// `StatusCode::CONFLICT` if the host accepted the stop but still reports the zone running.
+pub fn stop_zone(ip: String, id: i8) -> Result<(), DeviceError> {
+    try_set_zone(ip.clone(), false, id as i64)?;
+    if is_dry_run() {
+        return Ok(());
+    }
+    match get_zone_remaining(&ip, id)? {
+        Some(remaining) => Err(DeviceError::Http(
+            StatusCode::CONFLICT,
+            format!("zone {} on {} still has {:?} left after being stopped", id, ip, remaining),
+        )),
+        None => Ok(()),
+    }
 }
 
 /// Sets the sprinkler system on/off
+/// # Return
+/// True if the host accepted the change, see `try_set_system` for why it did not.
 pub fn set_system(ip: String, state: bool) -> bool {
-    let url = format!("http://{}:3030/system/state", ip);
+    match try_set_system(ip, state) {
+        Ok(..) => true,
+        Err(e) => {
+            debug!("Error: {}", e);
+            false
+        }
+    }
+}
+
+/// Sets the sprinkler system on/off.
+/// # Params
+/// * `ip` The IP Address of the SQLSprinkler host.
+/// * `state` Whether or not the system should be enabled.
+/// # Return
+/// Ok if the host accepted the change, `DeviceError::Http` with the error message of the host if it did not, or
+/// `DeviceError::Network` if the host could not be reached.
+pub fn try_set_system(ip: String, state: bool) -> Result<(), DeviceError> {
+    try_set_system_with_transport(&IsahcTransport, ip, state, &HostRequest::default())
+}
+
+/// Sets the sprinkler system on/off, sending the request through the given transport as the given request says. See
+/// `try_set_system`.
+pub fn try_set_system_with_transport(
+    transport: &dyn HttpTransport,
+    ip: String,
+    state: bool,
+    request: &HostRequest,
+) -> Result<(), DeviceError> {
+    let url = request.url(&ip, "system/state");
 
     let system_state = SystemToggle {
         system_enabled: state,
     };
 
-    let status = match Request::put(url)
-        .header("content-type", "application/json")
-        .body(serde_json::to_vec(&system_state).unwrap())
-        .timeout(std::time::Duration::from_secs(3))
-        .unwrap()
-        .send()
-    {
-        Ok(..) => true,
-        Err(..) => false,
-    };
-    status
+    put_to_host(transport, &url, serde_json::to_vec(&system_state).unwrap(), request)
+}
+
+/// Sets a rain delay on the sprinkler system, which keeps every zone from running for the given amount of hours. A
+/// delay of 0 hours cancels the current delay.
+/// # Params
+/// * `ip` The IP Address of the SQLSprinkler host.
+/// * `hours` How long zones should be kept from running.
+/// # Return
+/// Ok if the host accepted the change, `DeviceError::Http` with the error message of the host if it did not, or
+/// `DeviceError::Network` if the host could not be reached.
+pub fn set_rain_delay(ip: String, hours: u32) -> Result<(), DeviceError> {
+    set_rain_delay_with_transport(&IsahcTransport, ip, hours, &HostRequest::default())
+}
+
+/// Sets a rain delay on the sprinkler system, sending the request through the given transport as the given request
+/// says. See `set_rain_delay`.
+pub fn set_rain_delay_with_transport(
+    transport: &dyn HttpTransport,
+    ip: String,
+    hours: u32,
+    request: &HostRequest,
+) -> Result<(), DeviceError> {
+    let url = request.url(&ip, "system/rain_delay");
+
+    let rain_delay = RainDelay { hours };
+
+    put_to_host(transport, &url, serde_json::to_vec(&rain_delay).unwrap(), request)
+}
+
+/// Gets how many hours are left on the rain delay of the SQLSprinkler host.
+/// # Params
+/// * `ip` The IP Address of the SQLSprinkler host.
+/// * `timeout` How long to wait for the host to answer.
+/// # Return
+/// The hours left on the delay, 0 if there is no delay, `DeviceError::Http` if the host answered with an error status,
+/// or another error if something happened.
+pub fn get_rain_delay_from_sqlsprinkler(ip: &String, timeout: Duration) -> Result<u32, DeviceError> {
+    get_rain_delay_from_sqlsprinkler_with_transport(&IsahcTransport, ip, timeout)
+}
+
+/// Gets how many hours are left on the rain delay of the SQLSprinkler host, sending the request through the given
+/// transport. See `get_rain_delay_from_sqlsprinkler`.
+pub fn get_rain_delay_from_sqlsprinkler_with_transport(
+    transport: &dyn HttpTransport,
+    ip: &String,
+    timeout: Duration,
+) -> Result<u32, DeviceError> {
+    let url = format!("http://{}:3030/system/rain_delay", ip);
+    let response = transport.get(&url, timeout)?;
+    if !response.status.is_success() {
+        return Err(DeviceError::Http(response.status, error_message(&response.body)));
+    }
+    let rain_delay: RainDelay = serde_json::from_str(&response.body).map_err(deserialize)?;
+    Ok(rain_delay.hours)
+}
+
+/// Sets the zone status to the given state, refusing to turn the zone on while the system has a rain delay. Turning a
+/// zone off is always allowed.
+/// # Params
+/// * `ip` The IP Address of the SQLSprinkler host.
+/// * `state` Whether the zone should be on or off.
+/// * `id` The id of the zone.
+/// # Return
+/// Ok if the zone was set, `DeviceError::Unsupported` during a rain delay, or the error from the host.
+pub fn set_zone_unless_rain_delay(ip: String, state: bool, id: i64) -> Result<(), DeviceError> {
+    if state {
+        let hours = get_rain_delay_from_sqlsprinkler(&ip, DEFAULT_REQUEST_TIMEOUT)?;
+        if hours > 0 {
+            return Err(DeviceError::Unsupported(format!(
+                "turning on zones during a rain delay ({} hours left)",
+                hours
+            )));
+        }
+    }
+    try_set_zone(ip, state, id)
+}
+
+/// Sets how long the given zone runs for when it is turned on.
+/// # Params
+/// * `ip` The IP Address of the SQLSprinkler host.
+/// * `id` The id of the zone.
+/// * `seconds` How long the zone should run for.
+/// # Return
+/// Ok if the host accepted the change, `DeviceError::Http` with the error message of the host if it did not, or
+/// `DeviceError::Network` if the host could not be reached.
+pub fn set_zone_time(ip: String, id: i64, seconds: u64) -> Result<(), DeviceError> {
+    set_zone_time_with_transport(&IsahcTransport, ip, id, seconds, &HostRequest::default())
+}
+
+/// Sets how long the given zone runs for, sending the request through the given transport as the given request says.
+/// See `set_zone_time`.
+pub fn set_zone_time_with_transport(
+    transport: &dyn HttpTransport,
+    ip: String,
+    id: i64,
+    seconds: u64,
+    request: &HostRequest,
+) -> Result<(), DeviceError> {
+    let url = request.url(&ip, "zone/time");
+
+    let zone_time = ZoneTime { id, time: seconds };
+
+    put_to_host(transport, &url, serde_json::to_vec(&zone_time).unwrap(), request)
+}
+
+/// Enables or disables the given zone. A disabled zone keeps its config, but is skipped when the system runs.
+/// # Params
+/// * `ip` The IP Address of the SQLSprinkler host.
+/// * `id` The id of the zone.
+/// * `enabled` Whether or not the zone should be part of the rotation.
+/// # Return
+/// Ok if the host accepted the change, `DeviceError::Http` with the error message of the host if it did not, or
+/// `DeviceError::Network` if the host could not be reached.
+pub fn set_zone_enabled(ip: String, id: i64, enabled: bool) -> Result<(), DeviceError> {
+    set_zone_enabled_with_transport(&IsahcTransport, ip, id, enabled, &HostRequest::default())
+}
+
+/// Enables or disables the given zone, sending the request through the given transport as the given request says.
+/// See `set_zone_enabled`.
+pub fn set_zone_enabled_with_transport(
+    transport: &dyn HttpTransport,
+    ip: String,
+    id: i64,
+    enabled: bool,
+    request: &HostRequest,
+) -> Result<(), DeviceError> {
+    let url = request.url(&ip, "zone/enabled");
+
+    let zone_enabled = ZoneEnabled { id, enabled };
+
+    put_to_host(transport, &url, serde_json::to_vec(&zone_enabled).unwrap(), request)
+}
+
+/// Sends the given JSON body to the SQLSprinkler host, as the given request says. In dry-run mode the request is only
+/// logged.
+/// # Return
+/// Ok if the host accepted it, `DeviceError::Http` with the error message of the host if it did not.
+fn put_to_host(
+    transport: &dyn HttpTransport,
+    url: &str,
+    body: Vec<u8>,
+    request: &HostRequest,
+) -> Result<(), DeviceError> {
+    if is_dry_run() {
+        info!("[dry-run] PUT {} {}", url, String::from_utf8_lossy(&body));
+        return Ok(());
+    }
+    let response = transport.put_json_with_options(url, body, request.timeout, &request.options)?;
+    if response.status.is_success() {
+        return Ok(());
+    }
+    Err(DeviceError::Http(response.status, error_message(&response.body)))
+}
+
+/// Gets the error message out of an error body from the SQLSprinkler host. The host answers with
+/// `{"error": "..."}` or `{"message": "..."}`, anything else is returned as-is.
+fn error_message(body: &str) -> String {
+    match serde_json::from_str::<Value>(body) {
+        Ok(json) => match json.get("error").or_else(|| json.get("message")) {
+            Some(Value::String(message)) => message.clone(),
+            Some(message) => message.to_string(),
+            None => body.trim().to_string(),
+        },
+        Err(..) => body.trim().to_string(),
+    }
+}
+
+/// Gets the status from the SQLSprinkler host, giving up after `DEFAULT_REQUEST_TIMEOUT`.
+/// # Params
+/// * `ip` The IP Address of the SQLSprinkler host.
+/// # Return
+/// A boolean representing the state of the SQLSprinkler host, or an error if something happened.
+pub fn get_status_from_sqlsprinkler(ip: &String) -> Result<bool, DeviceError> {
+    get_status_from_sqlsprinkler_with_timeout(ip, DEFAULT_REQUEST_TIMEOUT)
 }
 
 /// Gets the status from the SQLSprinkler host
 /// # Params
 /// * `ip` The IP Address of the SQLSprinkler host.
+/// * `timeout` How long to wait for the host to answer.
 /// # Return
 /// A boolean representing the state of the SQLSprinkler host, or an error if something happened.
-pub(crate) fn get_status_from_sqlsprinkler(ip: &String) -> Result<bool, Box<dyn Error>> {
+pub fn get_status_from_sqlsprinkler_with_timeout(
+    ip: &String,
+    timeout: Duration,
+) -> Result<bool, DeviceError> {
+    get_status_from_sqlsprinkler_with_transport(&IsahcTransport, ip, timeout)
+}
+
+/// Gets the status from the SQLSprinkler host, sending the request through the given transport.
+/// # Params
+/// * `transport` What to send the request with.
+/// * `ip` The IP Address of the SQLSprinkler host.
+/// * `timeout` How long to wait for the host to answer.
+/// # Return
+/// A boolean representing the state of the SQLSprinkler host, `DeviceError::Http` if the host answered with an error
+/// status, or another error if something happened.
+pub fn get_status_from_sqlsprinkler_with_transport(
+    transport: &dyn HttpTransport,
+    ip: &String,
+    timeout: Duration,
+) -> Result<bool, DeviceError> {
     let url = format!("http://{}:3030/system/state", ip);
-    let response = match isahc::get(url).timeout(std::time::Duration::from_secs(3)).unwrap() {
-        Ok(mut res) => res.text().unwrap(),
-        Err(..) => "".to_string(),
-    };
+    let response = transport.get(&url, timeout)?;
+    // Error pages (ie the 503 the host answers with while starting up) aren't a system state.
+    if !response.status.is_success() {
+        return Err(DeviceError::Http(response.status, error_message(&response.body)));
+    }
 
-    match response.as_str() {
+    match response.body.as_str() {
         "" => return Ok(false),
         _ => {
-            let system_status: SystemToggle = serde_json::from_str(&response).unwrap();
+            let system_status: SystemToggle = serde_json::from_str(&response.body).map_err(deserialize)?;
             Ok(system_status.system_enabled)
         }
     }
 }
 
-/// Gets all the zones from the SQLSprinkler host.
+/// Gets all the zones from the SQLSprinkler host, giving up after the given timeout.
 /// # Params
 /// * `ip` A string representing the IP address of the SQLSprinkler host.
+/// * `timeout` How long to wait for the host to answer.
 /// # Returns
 /// * A `Vec<Zone>` Representing all of the SQLSprinkler zones on the given host.  Or, if an
 /// error occurs, we will get that error.
-fn get_zones_from_sqlsprinkler(ip: &String) -> Result<Vec<Zone>, Box<dyn Error>> {
+pub(crate) fn get_zones_from_sqlsprinkler_with_timeout(
+    ip: &String,
+    timeout: Duration,
+) -> Result<Vec<Zone>, DeviceError> {
+    get_zones_from_sqlsprinkler_with_transport(&IsahcTransport, ip, timeout)
+}
+
+/// Gets all the zones from the SQLSprinkler host, sending the request through the given transport.
+/// # Params
+/// * `transport` What to send the request with.
+/// * `ip` A string representing the IP address of the SQLSprinkler host.
+/// * `timeout` How long to wait for the host to answer.
+/// # Returns
+/// * The zones of the host, `DeviceError::Http` if the host answered with an error status, or another error if
+/// something happened.
+pub fn get_zones_from_sqlsprinkler_with_transport(
+    transport: &dyn HttpTransport,
+    ip: &String,
+    timeout: Duration,
+) -> Result<Vec<Zone>, DeviceError> {
     let url = format!("http://{}:3030/zone/info", ip);
 
-    //TODO: Make this less ugly.
-    let response = isahc::get(url).timeout(std::time::Duration::from_secs(3)).unwrap().text().unwrap();;
-    let zone_list: Vec<Zone> = serde_json::from_str(&response).unwrap();
+    let response = transport.get(&url, timeout)?;
+    if !response.status.is_success() {
+        return Err(DeviceError::Http(response.status, error_message(&response.body)));
+    }
+    let zone_list: Vec<Zone> = serde_json::from_str(&response.body).map_err(deserialize)?;
 
     Ok(zone_list)
 }
 
+/// Gets how much longer the given zone on the SQLSprinkler host will run for.
+/// # Params
+/// * `ip` The IP Address of the SQLSprinkler host.
+/// * `id` The id of the zone.
+/// # Return
+/// The time left on the zone's run, None if the zone is idle, or an error if something happened.
+pub fn get_zone_remaining(ip: &String, id: i8) -> Result<Option<Duration>, DeviceError> {
+    Ok(get_zone_state(ip, id)?.remaining())
+}
+
+/// Gets the live state of a single zone on the SQLSprinkler host.
+/// # Params
+/// * `ip` The IP Address of the SQLSprinkler host.
+/// * `id` The id of the zone.
+/// # Return
+/// The zone, `DeviceError::NotFound` if the host has no such zone, or another error if something happened.
+pub fn get_zone_state(ip: &str, id: i8) -> Result<Zone, DeviceError> {
+    get_zone_state_with_timeout(ip, id, DEFAULT_REQUEST_TIMEOUT)
+}
+
+/// Gets the live state of a single zone on the SQLSprinkler host, giving up after the given timeout. The host has no
+/// per-zone endpoint, so this fetches every zone and keeps the one asked for.
+fn get_zone_state_with_timeout(ip: &str, id: i8, timeout: Duration) -> Result<Zone, DeviceError> {
+    let zone_list = get_zones_from_sqlsprinkler_with_timeout(&ip.to_string(), timeout)?;
+    match zone_list.into_iter().find(|zone| zone.id == id) {
+        Some(zone) => Ok(zone),
+        None => Err(DeviceError::NotFound(format!("zone {} on {}", id, ip))),
+    }
+}
+
 /// Checks to see if the given device is an SQLSprinkler Host.  If it is, push the zones that are
 /// connected to that SQLSprinkler host.
 /// # Params
@@ -121,60 +577,382 @@ fn get_zones_from_sqlsprinkler(ip: &String) -> Result<Vec<Zone>, Box<dyn Error>>
 /// # Return
 /// * True if the device is a sqlsprinkler host.
 pub fn check_if_device_is_sqlsprinkler_host(dev: Device) -> Vec<Device> {
-    let mut device_list = Vec::new();
-
     if dev.kind != DeviceType::SqlSprinklerHost {
-        return device_list;
+        return Vec::new();
     }
 
     let ip = &dev.ip;
-    let sprinkler_list = get_zones_from_sqlsprinkler(ip).unwrap();
+    match get_zones_from_sqlsprinkler_with_timeout(ip, dev.request_timeout()) {
+        Ok(sprinkler_list) => zone_devices(&dev, sprinkler_list),
+        Err(e) => {
+            warn!("Could not get the zones of {}: {}", dev.guid, e);
+            Vec::new()
+        }
+    }
+}
 
-    for zone in sprinkler_list {
+/// Makes the zone devices of the given SQLSprinkler host out of the zones it reported.
+/// # Params
+/// * `host` The SQLSprinkler host device.
+/// * `zones` The zones the host reported, ie the ones `fetch_host_state` got along with its state.
+/// # Return
+/// * One device for each zone, with a guid in the form of hostguid-zoneid.
+pub(crate) fn zone_devices(host: &Device, zones: Vec<Zone>) -> Vec<Device> {
+    let mut device_list = Vec::new();
+    for zone in zones {
         // Create a device from a sprinkler zone
         let mut sprinkler_device = Device::from(zone);
 
         // Make a new guid in the form of deviceguid-zoneid
-        let new_guid = format!("{}-{}", dev.guid, sprinkler_device.guid);
+        let new_guid = format!("{}-{}", host.guid, sprinkler_device.guid);
         sprinkler_device.guid = new_guid;
-        sprinkler_device.ip = dev.ip.to_string();
+        connect_zone_to_host(&mut sprinkler_device, host);
 
         device_list.push(sprinkler_device);
     }
     device_list
 }
 
+/// Updates the `last_state` of the SQLSprinkler hosts in the given devices, and of their zones, with what the hosts
+/// report right now. Nothing else is fetched, so this is cheaper than getting the devices again. Hosts that can't be
+/// reached keep the state they had, along with their zones, and the other hosts are still refreshed.
+/// # Example
+///```
+/// use aa_models::device::{Device, DeviceType};
+/// use aa_models::sqlsprinkler::refresh_sqlsprinkler_state;
+/// use serde_json::json;
+///
+/// let mut host = Device::default();
+/// host.kind = DeviceType::SqlSprinklerHost;
+/// host.guid = String::from("0b5a2b5c-aabb-4f5e-9c3d-1234567890ab");
+/// host.ip = String::from("127.0.0.1:9");
+/// host.last_state = json!({"on": true});
+/// let mut devices = vec![host];
+/// // Nothing answers on this port, so the cached state is kept.
+/// assert!(refresh_sqlsprinkler_state(&mut devices).is_err());
+/// assert_eq!(json!({"on": true}), devices[0].last_state);
+/// ```
+/// # Return
+/// Ok if every host was refreshed, or the error of the first host that couldn't be.
+pub fn refresh_sqlsprinkler_state(devices: &mut [Device]) -> Result<(), DeviceError> {
+    refresh_sqlsprinkler_state_with_transport(&IsahcTransport, devices)
+}
+
+/// Updates the `last_state` of the SQLSprinkler hosts in the given devices, and of their zones, sending the requests
+/// through the given transport. See `refresh_sqlsprinkler_state`.
+/// # Example
+///```
+/// use std::time::Duration;
+/// use isahc::http::StatusCode;
+/// use aa_models::device::{Device, DeviceType};
+/// use aa_models::error::DeviceError;
+/// use aa_models::sqlsprinkler::refresh_sqlsprinkler_state_with_transport;
+/// use aa_models::transport::{HttpResponse, HttpTransport};
+/// use serde_json::json;
+///
+/// struct RunningHost;
+///
+/// impl HttpTransport for RunningHost {
+///     fn get(&self, url: &str, _timeout: Duration) -> Result<HttpResponse, DeviceError> {
+///         let body = if url.ends_with("/system/state") {
+///             r#"{"system_enabled": true}"#
+///         } else if url.ends_with("/zone/info") {
+///             r#"[{"name": "Front", "gpio": 4, "time": 10, "enabled": true, "auto_off": true,
+///                  "system_order": 0, "state": true, "id": 1}]"#
+///         } else {
+///             r#"{"hours": 0}"#
+///         };
+///         Ok(HttpResponse { status: StatusCode::OK, body: String::from(body) })
+///     }
+///
+///     fn put_json(&self, _url: &str, _body: Vec<u8>, _timeout: Duration) -> Result<HttpResponse, DeviceError> {
+///         unreachable!()
+///     }
+/// }
+///
+/// let mut host = Device::default();
+/// host.kind = DeviceType::SqlSprinklerHost;
+/// host.guid = String::from("0b5a2b5c-aabb-4f5e-9c3d-1234567890ab");
+/// host.ip = String::from("10.0.0.2");
+/// let mut devices = vec![host];
+/// assert!(refresh_sqlsprinkler_state_with_transport(&RunningHost, &mut devices).is_ok());
+/// assert_eq!(json!({"on": true, "current_zone": 1, "rain_delay_hours": 0}), devices[0].last_state);
+/// ```
+pub fn refresh_sqlsprinkler_state_with_transport(
+    transport: &dyn HttpTransport,
+    devices: &mut [Device],
+) -> Result<(), DeviceError> {
+    let hosts: Vec<(String, String, Duration)> = devices
+        .iter()
+        .filter(|dev| dev.kind == DeviceType::SqlSprinklerHost)
+        .map(|host| (host.guid.clone(), host.ip.clone(), host.request_timeout()))
+        .collect();
+
+    let mut first_error = None;
+    for (host_guid, ip, timeout) in hosts {
+        let (host_state, zones) = match fetch_host_state_with_transport(transport, &ip, timeout) {
+            Ok(live) => live,
+            Err(e) => {
+                warn!("Could not refresh {}, keeping its cached state: {}", host_guid, e);
+                if first_error.is_none() {
+                    first_error = Some(e);
+                }
+                continue;
+            }
+        };
+        for dev in devices.iter_mut() {
+            if dev.guid == host_guid {
+                dev.last_state = host_state.clone();
+                dev.stale = false;
+                continue;
+            }
+            let id = match ZONE_GUID.captures(&dev.guid) {
+                Some(captures) if captures[1] == host_guid => captures[2].parse::<i8>().ok(),
+                _ => continue,
+            };
+            match zones.iter().find(|zone| Some(zone.id) == id) {
+                Some(zone) => {
+                    dev.last_state = Device::from(zone).last_state;
+                    dev.stale = false;
+                }
+                None => debug!("{} no longer has zone {}", host_guid, dev.guid),
+            }
+        }
+    }
+    match first_error {
+        Some(e) => Err(e),
+        None => Ok(()),
+    }
+}
+
+/// Gets the live state of the SQLSprinkler host at the given IP address, in the shape its `last_state` is stored in,
+/// along with its zones. Fails if any of the status, the zones or the rain delay of the host can't be read.
+pub(crate) fn fetch_host_state(ip: &String, timeout: Duration) -> Result<(Value, Vec<Zone>), DeviceError> {
+    fetch_host_state_with_transport(&IsahcTransport, ip, timeout)
+}
+
+/// Gets the live state of the SQLSprinkler host at the given IP address along with its zones, sending the requests
+/// through the given transport. See `fetch_host_state`.
+fn fetch_host_state_with_transport(
+    transport: &dyn HttpTransport,
+    ip: &String,
+    timeout: Duration,
+) -> Result<(Value, Vec<Zone>), DeviceError> {
+    let enabled = get_status_from_sqlsprinkler_with_transport(transport, ip, timeout)?;
+    let zones = get_zones_from_sqlsprinkler_with_transport(transport, ip, timeout)?;
+    let current_zone = zones.iter().find(|zone| zone.state).map(|zone| zone.id);
+    // A failed read is not the host having no rain delay, so it fails the whole state instead of reporting 0.
+    let rain_delay_hours = get_rain_delay_from_sqlsprinkler_with_transport(transport, ip, timeout)?;
+    let state = json!({
+        "on": enabled,
+        "current_zone": current_zone,
+        "rain_delay_hours": rain_delay_hours
+    });
+    Ok((state, zones))
+}
+
+/// Gets the faults of every zone on the given SQLSprinkler host, for the `currentStatusReport` of the host.
+/// # Params
+/// * `dev` The SQLSprinkler host device.
+/// # Return
+/// The status report of every faulted zone, each targeting the zone device.
+pub fn get_host_status_report(dev: &Device) -> Vec<Value> {
+    check_if_device_is_sqlsprinkler_host(dev.clone())
+        .iter()
+        .flat_map(|zone| zone.get_status_report())
+        .collect()
+}
+
+/// Matches the guid of a SQLSprinkler zone: the guid of its host, a dash, and the id of the zone. The host guid and
+/// the zone id are captured.
+static ZONE_GUID: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(r"^([0-9A-Fa-f]{8}-?(?:[0-9A-Fa-f]{4}-?){3}[0-9A-Fa-f]{12})-([0-9]+)$").unwrap()
+});
+
 /// Checks to see if the given guid is a SQLSprinkler zone.
+/// # Example
+///```
+/// use aa_models::sqlsprinkler::check_if_zone;
+///
+/// let cases = [
+///     // Zones, with upper or lower case host guids, with or without dashes.
+///     ("0b5a2b5c-aabb-4f5e-9c3d-1234567890ab-1", true),
+///     ("0B5A2B5C-AABB-4F5E-9C3D-1234567890AB-2", true),
+///     ("0b5a2b5caabb4f5e9c3d1234567890ab-3", true),
+///     ("0b5a2b5c-aabb-4f5e-9c3d-1234567890ab-120", true),
+///     // Plain devices.
+///     ("0b5a2b5c-aabb-4f5e-9c3d-1234567890ab", false),
+///     ("test_switch", false),
+///     ("", false),
+///     // Near misses.
+///     ("0b5a2b5c-aabb-4f5e-9c3d-1234567890ab-", false),
+///     ("0b5a2b5c-aabb-4f5e-9c3d-1234567890ab-1x", false),
+///     ("0b5a2b5c-aabb-4f5e-9c3d-1234567890zz-1", false),
+///     ("x0b5a2b5c-aabb-4f5e-9c3d-1234567890ab-1", false),
+///     ("test_switch\n0b5a2b5c-aabb-4f5e-9c3d-1234567890ab-1", false),
+/// ];
+/// for (guid, is_zone) in cases.iter() {
+///     assert_eq!(*is_zone, check_if_zone(&guid.to_string()), "{}", guid);
+/// }
+/// ```
 /// # Param
 /// * `guid`  The GUID of the device we are checking.
 /// # Return
 /// True if there is a match to the pattern of a SQLSprinkler zone.
 pub fn check_if_zone(guid: &String) -> bool {
-    let re =
-        Regex::new(r"(?im)^[0-9A-Fa-f]{8}[-]?(?:[0-9A-Fa-f]{4}[-]?){3}[0-9A-Fa-f]{12}[-][0-9].?$")
-            .unwrap();
-    re.is_match(guid.as_str())
+    ZONE_GUID.is_match(guid.as_str())
+}
+
+/// Gets the guid of the SQLSprinkler host of the given zone guid.
+/// # Example
+///```
+/// use aa_models::sqlsprinkler::zone_host_guid;
+/// assert_eq!(
+///     Some(String::from("0b5a2b5c-aabb-4f5e-9c3d-1234567890ab")),
+///     zone_host_guid("0b5a2b5c-aabb-4f5e-9c3d-1234567890ab-12")
+/// );
+/// assert_eq!(None, zone_host_guid("test_switch"));
+/// ```
+/// # Return
+/// The guid of the host, or None if the given guid isn't the guid of a zone.
+pub fn zone_host_guid(guid: &str) -> Option<String> {
+    ZONE_GUID.captures(guid).map(|captures| captures[1].to_string())
 }
 
 /// Gets a Zone(as a Device) from the given GUID.
+/// # Return
+/// The zone, or a default device if it could not be found, see `try_get_zone` for why.
 pub fn get_zone(guid: &String) -> Device {
-    let host_guid = &guid[0..36];
+    match try_get_zone(guid) {
+        Ok(zone_device) => zone_device,
+        Err(e) => {
+            debug!("Could not get zone {}: {}", guid, e);
+            Device::default()
+        }
+    }
+}
+
+/// Gets a Zone(as a Device) from the given GUID, asking the host stored in firebase for the zone. The host is only
+/// read, it isn't refreshed or written back.
+/// # Example
+///```
+/// use aa_models::error::DeviceError;
+/// use aa_models::sqlsprinkler::try_get_zone;
+///
+/// assert!(matches!(try_get_zone(&String::from("test_switch")), Err(DeviceError::NotFound(_))));
+/// ```
+/// # Params
+/// * `guid` The GUID of the zone, the guid of its host, a dash, and the id of the zone.
+/// # Return
+/// The zone, `DeviceError::NotFound` if the guid isn't a zone guid or the host has no such zone, the error of
+/// `read_stored_device` if the host could not be read (`DeviceError::NotFound` if there is no host), or the error from
+/// the host if it could not be asked.
+pub fn try_get_zone(guid: &String) -> Result<Device, DeviceError> {
+    let captures = ZONE_GUID
+        .captures(guid)
+        .ok_or_else(|| DeviceError::NotFound(guid.clone()))?;
+    let id = captures[2]
+        .parse::<i8>()
+        .map_err(|_| DeviceError::NotFound(guid.clone()))?;
+    let host_guid = &captures[1];
     debug!("Host guid: {}", host_guid);
-    let host_device = get_device_from_guid(&host_guid.to_string());
-    let reg =
-        Regex::new(r"(?im)^[0-9A-Fa-f]{8}[-]?(?:[0-9A-Fa-f]{4}[-]?){3}[0-9A-Fa-f]{12}[-]").unwrap();
+    let host_device = read_stored_device(&host_guid.to_string())?;
+    debug!("Got SQLSprinkler host device with IP: {}", &host_device.ip);
+    let zone = get_zone_state_with_timeout(&host_device.ip, id, host_device.request_timeout())?;
+    let mut zone_device = Device::from(zone);
+    connect_zone_to_host(&mut zone_device, &host_device);
+    Ok(zone_device)
+}
 
-    let id_vec: Vec<String> = reg.split(&guid).map(|x| x.to_string()).collect();
+/// Gives the given zone device what it needs to send requests to its host: the IP address, request timeout, scheme,
+/// auth token and certificate options of the host.
+fn connect_zone_to_host(zone_device: &mut Device, host: &Device) {
+    zone_device.ip = host.ip.clone();
+    zone_device.request_timeout_secs = host.request_timeout_secs;
+    zone_device.https = host.https;
+    zone_device.auth_token = host.auth_token.clone();
+    zone_device.accept_self_signed_cert = host.accept_self_signed_cert;
+}
 
-    let id = id_vec[1].parse::<i64>().unwrap() as i8;
-    debug!("Got SQLSprinkler host device with IP: {}", &host_device.ip);
-    let sprinkler_list = get_zones_from_sqlsprinkler(&host_device.ip).unwrap();
-    for zone in sprinkler_list {
-        if zone.id == id {
-            let mut zone_device = Device::from(zone);
-            zone_device.ip = host_device.ip.clone();
-            return zone_device;
+/// Finds the zone guids in the given user's device list whose zone no longer exists on its SQLSprinkler host (the
+/// zone was removed from the host, or the host itself was removed from firebase). Hosts that can't be reached are
+/// skipped, so an outage never makes valid zones look like orphans. Nothing is removed, see `remove_missing_zones`.
+/// # Example
+///```
+/// use aa_models::sqlsprinkler::prune_missing_zones;
+/// let orphans = prune_missing_zones(&String::from("eoqBGbi9AHUxtOrL3xpJZDQotGP2"));
+/// println!("{:?}", orphans);
+/// ```
+/// # Params
+/// * `user_uuid` The user whose device list is checked.
+/// # Return
+/// The orphaned zone guids, in the order they are in the device list.
+pub fn prune_missing_zones(user_uuid: &String) -> Vec<String> {
+    find_missing_zones(&get_device_list(user_uuid))
+}
+
+/// Removes the zone guids found by `prune_missing_zones` from the given user's device list.
+/// # Params
+/// * `user_uuid` The user whose device list is cleaned up.
+/// # Return
+/// The orphaned zone guids that were removed, which is empty if the device list could not be read or written.
+pub fn remove_missing_zones(user_uuid: &String) -> Vec<String> {
+    let device_list = match try_get_device_list(user_uuid) {
+        Ok(device_list) => device_list,
+        Err(e) => {
+            warn!("Not removing the orphaned zones of {}, its devices could not be read: {}", user_uuid, e);
+            return vec![];
         }
+    };
+    let orphans = find_missing_zones(&device_list);
+    if orphans.is_empty() {
+        return orphans;
+    }
+    let kept = device_list.into_iter().filter(|guid| !orphans.contains(guid)).collect();
+    if !set_device_list(user_uuid, kept) {
+        warn!("Could not remove the orphaned zones of {}", user_uuid);
+        return vec![];
     }
-    Device::default()
+    orphans
+}
+
+/// Finds the zone guids in the given device list whose zone no longer exists on its host.
+fn find_missing_zones(device_list: &[String]) -> Vec<String> {
+    let mut zones_by_host: HashMap<&str, Vec<(&String, Option<i8>)>> = HashMap::new();
+    for guid in device_list {
+        if let Some(captures) = ZONE_GUID.captures(guid) {
+            let host_guid = captures.get(1).unwrap().as_str();
+            let id = captures[2].parse::<i8>().ok();
+            zones_by_host.entry(host_guid).or_default().push((guid, id));
+        }
+    }
+
+    let mut orphans: Vec<&String> = vec![];
+    for (host_guid, zones) in zones_by_host {
+        let live_ids: Vec<i8> = match read_stored_device(&host_guid.to_string()) {
+            Ok(host) => match get_zones_from_sqlsprinkler_with_timeout(&host.ip, host.request_timeout()) {
+                Ok(live_zones) => live_zones.iter().map(|zone| zone.id).collect(),
+                Err(e) => {
+                    warn!("Not pruning the zones of {}, it could not be reached: {}", host_guid, e);
+                    continue;
+                }
+            },
+            Err(DeviceError::NotFound(_)) => vec![],
+            Err(e) => {
+                warn!("Not pruning the zones of {}, it could not be read: {}", host_guid, e);
+                continue;
+            }
+        };
+        orphans.extend(
+            zones
+                .into_iter()
+                .filter(|(_, id)| !id.map_or(false, |id| live_ids.contains(&id)))
+                .map(|(guid, _)| guid),
+        );
+    }
+    device_list
+        .iter()
+        .filter(|guid| orphans.contains(guid))
+        .cloned()
+        .collect()
 }
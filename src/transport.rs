@@ -0,0 +1,171 @@
+use std::fmt;
+use std::time::Duration;
+
+use isahc::config::SslOption;
+use isahc::http::StatusCode;
+use isahc::prelude::*;
+use isahc::Request;
+use serde::{Deserialize, Serialize};
+
+use crate::error::{http_error, network, DeviceError};
+
+/// What a device answered to a request.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HttpResponse {
+    /// The status the device answered with.
+    pub status: StatusCode,
+    /// The body the device answered with.
+    pub body: String,
+}
+
+/// A bearer token devices behind an auth proxy are sent requests with. It is stored with the device, but never printed,
+/// so it doesn't end up in logs.
+/// # Example
+/// ```
+/// use aa_models::transport::AuthToken;
+/// let token = AuthToken::new("s3cr3t");
+/// assert_eq!("AuthToken(***)", format!("{:?}", token));
+/// ```
+#[derive(Serialize, Deserialize, Clone, PartialEq, Eq)]
+#[serde(transparent)]
+pub struct AuthToken(String);
+
+impl AuthToken {
+    /// Creates an auth token.
+    pub fn new(token: &str) -> AuthToken {
+        AuthToken(token.to_string())
+    }
+
+    /// Gets the value of the `Authorization` header for this token.
+    pub(crate) fn header_value(&self) -> String {
+        format!("Bearer {}", self.0)
+    }
+}
+
+impl fmt::Debug for AuthToken {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "AuthToken(***)")
+    }
+}
+
+/// How a request to a device has to be sent, on top of its url and timeout.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct RequestOptions {
+    /// The bearer token to send the request with, for devices behind an auth proxy.
+    pub auth_token: Option<AuthToken>,
+    /// Whether or not to accept a self-signed certificate, see `Device::accepts_self_signed_cert`.
+    pub accept_self_signed_cert: bool,
+}
+
+/// Sends the HTTP requests made to devices. `IsahcTransport` is used by default, other transports can be given to
+/// the `_with_transport` functions (ie a mock, so tests don't need real hardware).
+/// # Example
+///```
+/// use std::time::Duration;
+/// use isahc::http::StatusCode;
+/// use aa_models::error::DeviceError;
+/// use aa_models::sqlsprinkler::get_status_from_sqlsprinkler_with_transport;
+/// use aa_models::transport::{HttpResponse, HttpTransport};
+///
+/// struct EnabledHost;
+///
+/// impl HttpTransport for EnabledHost {
+///     fn get(&self, _url: &str, _timeout: Duration) -> Result<HttpResponse, DeviceError> {
+///         Ok(HttpResponse { status: StatusCode::OK, body: String::from(r#"{"system_enabled": true}"#) })
+///     }
+///
+///     fn put_json(&self, _url: &str, _body: Vec<u8>, _timeout: Duration) -> Result<HttpResponse, DeviceError> {
+///         Ok(HttpResponse { status: StatusCode::OK, body: String::new() })
+///     }
+/// }
+///
+/// let enabled = get_status_from_sqlsprinkler_with_transport(&EnabledHost, &String::from("10.0.0.2"), Duration::from_secs(1));
+/// assert!(enabled.unwrap());
+/// ```
+pub trait HttpTransport {
+    /// Sends a GET request to the given url.
+    fn get(&self, url: &str, timeout: Duration) -> Result<HttpResponse, DeviceError>;
+
+    /// Sends a GET request to the given url with the given options. Transports that don't talk to real devices (ie
+    /// mocks) can leave this to `get`.
+    fn get_with_options(
+        &self,
+        url: &str,
+        timeout: Duration,
+        _options: &RequestOptions,
+    ) -> Result<HttpResponse, DeviceError> {
+        self.get(url, timeout)
+    }
+
+    /// Sends a PUT request with the given JSON body to the given url.
+    fn put_json(&self, url: &str, body: Vec<u8>, timeout: Duration) -> Result<HttpResponse, DeviceError>;
+
+    /// Sends a PUT request with the given JSON body to the given url with the given options. Transports that don't
+    /// talk to real devices (ie mocks) can leave this to `put_json`.
+    fn put_json_with_options(
+        &self,
+        url: &str,
+        body: Vec<u8>,
+        timeout: Duration,
+        _options: &RequestOptions,
+    ) -> Result<HttpResponse, DeviceError> {
+        self.put_json(url, body, timeout)
+    }
+}
+
+/// Sends requests over the network with isahc.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct IsahcTransport;
+
+impl HttpTransport for IsahcTransport {
+    fn get(&self, url: &str, timeout: Duration) -> Result<HttpResponse, DeviceError> {
+        self.get_with_options(url, timeout, &RequestOptions::default())
+    }
+
+    fn get_with_options(
+        &self,
+        url: &str,
+        timeout: Duration,
+        options: &RequestOptions,
+    ) -> Result<HttpResponse, DeviceError> {
+        let mut request = Request::get(url).timeout(timeout);
+        if options.accept_self_signed_cert {
+            request = request
+                .ssl_options(SslOption::DANGER_ACCEPT_INVALID_CERTS | SslOption::DANGER_ACCEPT_INVALID_HOSTS);
+        }
+        if let Some(token) = &options.auth_token {
+            request = request.header("Authorization", token.header_value());
+        }
+        let mut response = request.body(()).map_err(network)?.send().map_err(http_error)?;
+        Ok(HttpResponse {
+            status: response.status(),
+            body: response.text().map_err(network)?,
+        })
+    }
+
+    fn put_json(&self, url: &str, body: Vec<u8>, timeout: Duration) -> Result<HttpResponse, DeviceError> {
+        self.put_json_with_options(url, body, timeout, &RequestOptions::default())
+    }
+
+    fn put_json_with_options(
+        &self,
+        url: &str,
+        body: Vec<u8>,
+        timeout: Duration,
+        options: &RequestOptions,
+    ) -> Result<HttpResponse, DeviceError> {
+        let mut request = Request::put(url).header("content-type", "application/json").timeout(timeout);
+        if options.accept_self_signed_cert {
+            request = request
+                .ssl_options(SslOption::DANGER_ACCEPT_INVALID_CERTS | SslOption::DANGER_ACCEPT_INVALID_HOSTS);
+        }
+        if let Some(token) = &options.auth_token {
+            request = request.header("Authorization", token.header_value());
+        }
+        let mut response = request.body(body).map_err(network)?.send().map_err(http_error)?;
+        Ok(HttpResponse {
+            status: response.status(),
+            body: response.text().map_err(network)?,
+        })
+    }
+}
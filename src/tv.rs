@@ -1,8 +1,20 @@
-use std::process::Command;
+use std::fs;
+use std::io::Read;
+use std::net::UdpSocket;
+use std::process::{Command, Output, Stdio};
+use std::sync::Mutex;
+use std::thread;
+use std::time::{Duration, Instant};
 
+use isahc::prelude::*;
+use isahc::Request;
+use log::{debug, info, warn};
 use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
 
-use crate::device::Device;
+use crate::config::is_dry_run;
+use crate::device::{Device, DEFAULT_REQUEST_TIMEOUT};
+use crate::error::{deserialize, DeviceError};
 
 /// A struct representing the command output for getting the tv volume
 #[derive(Serialize, Deserialize, Debug)]
@@ -22,6 +34,8 @@ pub struct TvState {
     pub muted: bool,
     pub volume: u8,
     pub volumeMax: u8,
+    #[serde(default)]
+    pub currentInput: String,
 }
 
 impl ::std::default::Default for TvState {
@@ -31,42 +45,90 @@ impl ::std::default::Default for TvState {
             muted: false,
             volume: 0,
             volumeMax: 100,
+            currentInput: String::new(),
         }
     }
 }
 
+/// The inputs the TV can be switched to, as (key, name) pairs.
+pub const TV_INPUTS: [(&str, &str); 4] = [
+    ("HDMI_1", "HDMI 1"),
+    ("HDMI_2", "HDMI 2"),
+    ("HDMI_3", "HDMI 3"),
+    ("HDMI_4", "HDMI 4"),
+];
 
-/// Checks to see if the given device is a TV, if so, add the fields required for TV.
+/// A struct representing the command output for getting the app the TV is showing.
+#[derive(Serialize, Deserialize, Debug)]
+#[allow(non_snake_case)]
+struct InputState {
+    pub returnValue: bool,
+    pub appId: String,
+}
+
+
+/// Checks to see if the given device is a TV, if so, add the fields required for TV. The TV is given the request
+/// timeout of the device to answer, and a TV that doesn't answer in time (or at all) keeps the state it had. A TV that
+/// doesn't answer on its stored IP is looked for on the LAN, in case DHCP gave it a new one (see `relocate_tv`).
 /// # Param
 /// * dev : The Device we want to check to see if it is a TV.
 /// # Return
 /// True if the device is a TV, false otherwise.
 pub fn parse_device(mut dev: Device) -> Device {
     if dev.kind == crate::device::DeviceType::TV {
-        let is_online = dev.is_online();
+        let is_online = dev.is_online() || relocate_tv(&mut dev);
         // !!! ONLY QUERY TV WHEN IT IS ON !!!
         if is_online {
-            dev.last_state = serde_json::json!(get_tv_state());
+            match read_tv_state(dev.request_timeout()) {
+                Ok(state) => dev.last_state = serde_json::json!(state),
+                Err(e) => {
+                    warn!("Could not get the state of {}: {}", dev.guid, e);
+                    return dev;
+                }
+            }
         } else {
             dev.last_state = serde_json::json!(TvState::default())
         }
-        dev.database_update();
+        if let Err(e) = dev.database_update() {
+            debug!("Could not update {}: {}", dev.guid, e);
+        }
         return dev.clone();
     }
     dev.clone()
 }
 
+/// Looks for the given TV on the LAN after it stopped answering on its stored IP, and moves it to the IP it has now
+/// (see `find_tv_ip`). The new IP is stored right away, so it is kept even if the TV can't be read.
+/// # Return
+/// Whether or not the TV was found on a new IP and answers there.
+fn relocate_tv(dev: &mut Device) -> bool {
+    let ip = match find_tv_ip(dev) {
+        Some(ip) if ip != dev.ip => ip,
+        _ => return false,
+    };
+    info!("{} moved from {} to {}", dev.guid, dev.ip, ip);
+    dev.ip = ip;
+    if let Err(e) = dev.update_fields(&["ip"]) {
+        warn!("Could not store the new IP of {}: {}", dev.guid, e);
+    }
+    dev.is_online()
+}
+
 /// Allows setting TV volume to value
 #[derive(Serialize, Deserialize, Debug, Copy, Clone)]
-pub struct SetVolState(u8);
+pub struct SetVolState(pub u8);
 
 /// Allows toggling mute of TV
 #[derive(Serialize, Deserialize, Debug, Copy, Clone)]
-pub struct SetMuteState(bool);
+pub struct SetMuteState(pub bool);
 
 /// Allows turning on/off TV.
 #[derive(Serialize, Deserialize, Debug, Copy, Clone)]
-pub struct SetPowerState(bool);
+pub struct SetPowerState(pub bool);
+
+/// Allows switching the input of the TV, using a key from `TV_INPUTS`.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct SetInputState(pub String);
 
 /// The output of the requests to the tv.
 #[derive(Serialize, Deserialize, Debug)]
@@ -79,63 +141,551 @@ struct ReturnVal {
 /// # Param
 /// `state` A SetVolState containing the integer value of the volume we want to set the tv to.
 /// # Return
-/// The success of the command
+/// The success of the command, false if the TV did not answer in time or with something other than its return value.
 pub fn set_volume_state(state: SetVolState) -> bool {
-    let mut set_vol_command = Command::new("upstairs-tv");
-    let vol_output = set_vol_command.arg("set")
-        .arg("vol")
-        .arg(state.0.to_string())
-        .output().unwrap()
-        .stdout;
-    let vol_return_str = String::from_utf8(vol_output).unwrap();
-    let vol_return: ReturnVal = serde_json::from_str(vol_return_str.as_str()).unwrap();
-    vol_return.returnValue
+    let volume = state.0.to_string();
+    let args = ["set", "vol", volume.as_str()];
+    if skipped_for_dry_run(&args) {
+        return true;
+    }
+    let vol_return = run_tv_command(&args, DEFAULT_REQUEST_TIMEOUT)
+        .and_then(|output| serde_json::from_slice::<ReturnVal>(&output.stdout).map_err(deserialize));
+    match vol_return {
+        Ok(vol_return) => vol_return.returnValue,
+        Err(e) => {
+            warn!("Could not set the volume of the TV: {}", e);
+            false
+        }
+    }
+}
+
+/// Changes the volume of the TV by the given amount of steps, for google's `volumeRelative` command. The volume never
+/// goes below 0 or above the max volume of the TV, so turning it up near the max just sets it to the max.
+/// # Param
+/// * `dev` The TV, whose state holds the current volume.
+/// * `relative_steps` How many steps to change the volume by, negative to turn it down.
+/// # Return
+/// The success of the command
+pub fn set_volume_relative(dev: &Device, relative_steps: i32) -> bool {
+    let state: TvState = match serde_json::from_value(dev.last_state.clone()) {
+        Ok(state) => state,
+        Err(..) => get_tv_state(),
+    };
+    let volume = clamp_volume(state.volume, relative_steps, state.volumeMax);
+    set_volume_state(SetVolState(volume))
+}
+
+/// Adds the steps to the current volume, keeping it within `[0, max]`.
+fn clamp_volume(current: u8, relative_steps: i32, max: u8) -> u8 {
+    (current as i32 + relative_steps).max(0).min(max as i32) as u8
 }
 
 /// Sets the power of the TV to the requested value (true/on - false/off)
+/// # Return
+/// The success of the command, false if the TV did not answer in time.
 pub fn set_power_state(state: bool) -> bool {
-    Command::new("upstairs-tv")
-        .arg("set")
-        .arg("power")
-        .arg(state.to_string())
-        .status()
-        .unwrap()
-        .success()
+    send_tv_command(&["set", "power", &state.to_string()])
 }
 
 /// Sets the volume state of the TV to the given VolState
 /// # Param
 /// `state` A SetMuteState containing the integer value of the volume we want to set the tv to.
 /// # Return
-/// The success of the command
+/// The success of the command, false if the TV did not answer in time.
 pub fn set_mute_state(state: SetMuteState) -> bool {
-    Command::new("upstairs-tv")
-        .arg("set")
-        .arg("mute")
-        .arg(state.0.to_string())
-        .status()
-        .unwrap()
-        .success()
+    send_tv_command(&["set", "mute", &state.0.to_string()])
+}
+
+/// Switches the TV to the given input
+/// # Param
+/// `state` A SetInputState containing the key of the input we want to switch to, ie `HDMI_2`
+/// # Return
+/// The success of the command, false if the TV did not answer in time.
+pub fn set_input_state(state: SetInputState) -> bool {
+    send_tv_command(&["set", "input", &state.0])
+}
+
+/// Runs the given command for the TV, giving up after `DEFAULT_REQUEST_TIMEOUT`. In dry-run mode it is only logged.
+/// # Param
+/// `args` The args to run the TV command with, ie `["set", "power", "true"]`
+/// # Return
+/// True if the TV accepted the command (or dry-run mode is on), false otherwise.
+fn send_tv_command(args: &[&str]) -> bool {
+    if skipped_for_dry_run(args) {
+        return true;
+    }
+    match run_tv_command(args, DEFAULT_REQUEST_TIMEOUT) {
+        Ok(..) => true,
+        Err(e) => {
+            warn!("The TV did not accept upstairs-tv {}: {}", args.join(" "), e);
+            false
+        }
+    }
+}
+
+/// Logs the given command for the TV instead of running it when dry-run mode is on.
+/// # Param
+/// `args` The args the TV command would be run with, ie `["set", "vol", "10"]`
+/// # Return
+/// True if dry-run mode is on and the command should not be run.
+fn skipped_for_dry_run(args: &[&str]) -> bool {
+    if !is_dry_run() {
+        return false;
+    }
+    info!("[dry-run] upstairs-tv {}", args.join(" "));
+    true
+}
+
+/// How long `power_on_then_input` waits for the TV to come up.
+pub const POWER_ON_TIMEOUT: Duration = Duration::from_secs(20);
+
+/// How often `power_on_then_input` checks whether or not the TV came up.
+const POWER_ON_POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Turns the TV on, waits for it to be ready and then switches it to the given input. The TV ignores an input switch
+/// sent right after it is turned on, so the input is only switched once the TV answers.
+/// # Param
+/// `input` The key of the input we want to switch to, ie `HDMI_2`
+/// # Return
+/// The success of the command, false if the TV did not come up within `POWER_ON_TIMEOUT`.
+pub fn power_on_then_input(input: &str) -> bool {
+    if !set_power_state(true) {
+        return false;
+    }
+    // In dry-run mode the TV was never turned on, so there is nothing to wait for.
+    if is_dry_run() {
+        return set_input_state(SetInputState(input.to_string()));
+    }
+    let started = Instant::now();
+    while !is_tv_on() {
+        if started.elapsed() >= POWER_ON_TIMEOUT {
+            debug!("TV did not come up within {:?}, not switching to {}", POWER_ON_TIMEOUT, input);
+            return false;
+        }
+        thread::sleep(POWER_ON_POLL_INTERVAL);
+    }
+    set_input_state(SetInputState(input.to_string()))
+}
+
+/// Checks whether or not the TV is on and answering commands.
+fn is_tv_on() -> bool {
+    run_tv_command(&["get", "vol"], DEFAULT_REQUEST_TIMEOUT).is_ok()
+}
+
+/// What the TV can do, as found out by asking it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TvCapabilities {
+    /// The highest volume the TV can be set to.
+    pub volume_max: u8,
+    /// Whether or not the TV can tell (and switch) which input it is showing.
+    pub input_selector: bool,
+}
+
+impl TvCapabilities {
+    /// Gets what the TV can do from the state it last reported (see `parse_device`), for while it can't be asked. A TV
+    /// that never reported an input isn't advertised as being able to switch inputs.
+    /// # Example
+    ///```
+    /// use aa_models::tv::TvCapabilities;
+    /// use serde_json::json;
+    ///
+    /// let capabilities = TvCapabilities::from_state(&json!({"volumeMax": 50, "currentInput": "HDMI_1"}));
+    /// assert_eq!(TvCapabilities { volume_max: 50, input_selector: true }, capabilities);
+    /// let capabilities = TvCapabilities::from_state(&json!(false));
+    /// assert_eq!(TvCapabilities { volume_max: 100, input_selector: false }, capabilities);
+    /// ```
+    pub fn from_state(state: &Value) -> TvCapabilities {
+        let volume_max = match state["volumeMax"].as_u64() {
+            Some(volume_max) if volume_max > 0 => volume_max.min(u8::MAX as u64) as u8,
+            _ => TvState::default().volumeMax,
+        };
+        TvCapabilities {
+            volume_max,
+            input_selector: state["currentInput"].as_str().map_or(false, |input| !input.is_empty()),
+        }
+    }
+}
+
+/// The capabilities of the TV, once they have been found out by `discover_capabilities`.
+static CAPABILITIES: Mutex<Option<TvCapabilities>> = Mutex::new(None);
+
+/// Gets what the given TV can do, without asking it: what `discover_capabilities` found out, or what the state of the
+/// TV shows until then (see `TvCapabilities::from_state`). This is what trait and attribute lookups use, so they never
+/// wait on the TV.
+/// # Example
+///```
+/// use aa_models::device::{Device, DeviceType};
+/// use aa_models::tv;
+///
+/// let mut device = Device::default();
+/// device.kind = DeviceType::TV;
+/// assert!(tv::capabilities(&device).volume_max > 0);
+/// ```
+pub fn capabilities(dev: &Device) -> TvCapabilities {
+    match *CAPABILITIES.lock().unwrap() {
+        Some(capabilities) => capabilities,
+        None => TvCapabilities::from_state(&dev.last_state),
+    }
+}
+
+/// Finds out what the given TV can do, for SYNC. The TV is only asked once, and only when it is on: a TV that is off
+/// or doesn't answer gets what its state shows instead (see `TvCapabilities::from_state`). Either answer is kept for
+/// every later call, so the TV is never asked again.
+/// # Return
+/// What the TV can do.
+pub fn discover_capabilities(dev: &Device) -> TvCapabilities {
+    let mut cached = CAPABILITIES.lock().unwrap();
+    if let Some(capabilities) = *cached {
+        return capabilities;
+    }
+    // !!! ONLY QUERY TV WHEN IT IS ON !!!
+    let discovered = if dev.is_online() { probe_capabilities(dev.request_timeout()) } else { None };
+    let capabilities = discovered.unwrap_or_else(|| {
+        debug!("Could not ask {} what it can do, using its state", dev.guid);
+        TvCapabilities::from_state(&dev.last_state)
+    });
+    *cached = Some(capabilities);
+    capabilities
+}
+
+/// Asks the TV what it can do, giving up on each command after the given timeout.
+/// # Return
+/// The capabilities of the TV, or None if it did not answer.
+fn probe_capabilities(timeout: Duration) -> Option<TvCapabilities> {
+    let output = run_tv_command(&["get", "vol"], timeout).ok()?;
+    let vol_state: VolState = serde_json::from_slice(&output.stdout).ok()?;
+    // A TV that is still starting up can answer with a max volume of 0, which would make the volume unusable.
+    if vol_state.volumeMax == 0 {
+        return None;
+    }
+    let input_selector = match run_tv_command(&["get", "input"], timeout) {
+        Ok(output) => serde_json::from_slice::<InputState>(&output.stdout).is_ok(),
+        Err(..) => false,
+    };
+    Some(TvCapabilities {
+        volume_max: vol_state.volumeMax,
+        input_selector,
+    })
+}
+
+/// Gets the available inputs of the TV, as google wants them for the `availableInputs` attribute of the
+/// InputSelector trait.
+pub fn available_inputs() -> Value {
+    let inputs: Vec<Value> = TV_INPUTS
+        .iter()
+        .map(|(key, name)| {
+            json!({
+                "key": key,
+                "names": [{
+                    "lang": "en",
+                    "name_synonym": [name, key.to_lowercase().replace('_', "")]
+                }]
+            })
+        })
+        .collect();
+    Value::from(inputs)
+}
+
+/// Gets the input the TV is currently showing, giving up after the given timeout.
+/// # Return
+/// The key of the input, the id of the app if the TV is not showing an input, or an empty string if the TV can't be
+/// asked.
+fn get_current_input(timeout: Duration) -> String {
+    let output = match run_tv_command(&["get", "input"], timeout) {
+        Ok(output) => output.stdout,
+        Err(..) => return String::new(),
+    };
+    match serde_json::from_slice::<InputState>(&output) {
+        Ok(input) => input_from_app_id(&input.appId),
+        Err(..) => String::new(),
+    }
+}
+
+/// Converts the id of the app the TV is showing into an input key, ie `com.webos.app.hdmi2` to `HDMI_2`.
+fn input_from_app_id(app_id: &str) -> String {
+    match app_id.strip_prefix("com.webos.app.hdmi") {
+        Some(number) => format!("HDMI_{}", number),
+        None => app_id.to_string(),
+    }
 }
 
 /// Gets the volume states from the TV.
 /// # Return
-/// A VolState struct containing all of the information for the volume of the TV.
+/// A VolState struct containing all of the information for the volume of the TV, or the state of a TV that is off if
+/// the TV can't be asked or gives an answer that can't be read (so an unplugged TV never fails a whole device list).
 pub fn get_tv_state() -> TvState {
-    let mut output = Command::new("upstairs-tv");
-    output.arg("get")
-        .arg("vol");
-
-    let is_success = output.status().unwrap().success();
-    if is_success {
-        let data = String::from_utf8(output.output().unwrap().stdout).unwrap();
-        let vol_state: VolState = serde_json::from_str(data.as_str()).unwrap();
-        return TvState {
-            on: true,
-            muted: vol_state.muted,
-            volume: vol_state.volume,
-            volumeMax: vol_state.volumeMax,
+    get_tv_state_with_timeout(DEFAULT_REQUEST_TIMEOUT)
+}
+
+/// Gets the volume states from the TV, giving up after the given timeout. See `get_tv_state`.
+pub fn get_tv_state_with_timeout(timeout: Duration) -> TvState {
+    read_tv_state(timeout).unwrap_or_else(|e| {
+        debug!("Could not get the state of the TV: {}", e);
+        TvState::default()
+    })
+}
+
+/// Asks the TV for its state, giving up after the given timeout.
+/// # Return
+/// The state of the TV, `DeviceError::Timeout` if it did not answer in time, `DeviceError::Deserialize` if its answer
+/// can't be read, or `DeviceError::Network` if it could not be asked.
+fn read_tv_state(timeout: Duration) -> Result<TvState, DeviceError> {
+    let started = Instant::now();
+    let output = run_tv_command(&["get", "vol"], timeout)?;
+    let vol_state: VolState = serde_json::from_slice(&output.stdout).map_err(deserialize)?;
+    Ok(TvState {
+        on: true,
+        muted: vol_state.muted,
+        volume: vol_state.volume,
+        volumeMax: if vol_state.volumeMax == 0 { TvState::default().volumeMax } else { vol_state.volumeMax },
+        currentInput: get_current_input(timeout.checked_sub(started.elapsed()).unwrap_or_default()),
+    })
+}
+
+/// How often `run_tv_command` checks whether or not the command finished.
+const COMMAND_POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+/// Runs `upstairs-tv` with the given args, killing it if it is still running after the given timeout.
+/// # Return
+/// The output of the command, `DeviceError::Timeout` if it was killed, or `DeviceError::Network` if it could not be
+/// started or did not succeed.
+fn run_tv_command(args: &[&str], timeout: Duration) -> Result<Output, DeviceError> {
+    let command = format!("upstairs-tv {}", args.join(" "));
+    let mut child = Command::new("upstairs-tv")
+        .args(args)
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()
+        .map_err(|e| DeviceError::Network(format!("{}: {}", command, e)))?;
+    // Read the output while waiting, so a chatty TV can't fill the pipe and block.
+    let mut stdout = child.stdout.take();
+    let reader = thread::spawn(move || {
+        let mut output = vec![];
+        if let Some(stdout) = stdout.as_mut() {
+            let _ = stdout.read_to_end(&mut output);
+        }
+        output
+    });
+    let started = Instant::now();
+    let status = loop {
+        match child.try_wait() {
+            Ok(Some(status)) => break status,
+            Ok(None) if started.elapsed() < timeout => thread::sleep(COMMAND_POLL_INTERVAL),
+            Ok(None) => {
+                let _ = child.kill();
+                let _ = child.wait();
+                return Err(DeviceError::Timeout(format!("{} after {:?}", command, timeout)));
+            }
+            Err(e) => return Err(DeviceError::Network(format!("{}: {}", command, e))),
+        }
+    };
+    let stdout = reader.join().unwrap_or_default();
+    if !status.success() {
+        return Err(DeviceError::Network(format!("{} exited with {}", command, status)));
+    }
+    Ok(Output {
+        status,
+        stdout,
+        stderr: vec![],
+    })
+}
+
+/// Where SSDP searches are sent.
+const SSDP_ADDRESS: &str = "239.255.255.250:1900";
+
+/// The SSDP search target LG webOS TV's answer to.
+const WEBOS_SEARCH_TARGET: &str = "urn:lge-com:service:webos-second-screen:1";
+
+/// How long `find_tv_ip` listens for TV's to answer.
+pub const DISCOVERY_TIMEOUT: Duration = Duration::from_secs(3);
+
+/// An LG webOS TV that answered an SSDP search.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DiscoveredTv {
+    /// The IP the TV currently has.
+    pub ip: String,
+    /// The unique service name the TV answered with, ie `uuid:...::urn:lge-com:service:webos-second-screen:1`.
+    pub usn: String,
+    /// The name the TV gives itself, if its description could be read.
+    pub friendly_name: Option<String>,
+    /// The MAC address of the TV, if it is in the ARP table.
+    pub mac: Option<String>,
+}
+
+impl DiscoveredTv {
+    /// Checks whether or not this TV is the one with the given identifier, which is either its MAC address or its
+    /// friendly name. Neither is case sensitive, and MAC addresses may use `-` instead of `:`.
+    /// # Example
+    /// ```
+    /// use aa_models::tv::DiscoveredTv;
+    /// let tv = DiscoveredTv {
+    ///     ip: String::from("192.168.1.23"),
+    ///     usn: String::from("uuid:abc::urn:lge-com:service:webos-second-screen:1"),
+    ///     friendly_name: Some(String::from("Upstairs TV")),
+    ///     mac: Some(String::from("a8:23:fe:01:02:03")),
+    /// };
+    /// assert!(tv.matches("A8-23-FE-01-02-03"));
+    /// assert!(tv.matches("upstairs tv"));
+    /// assert!(!tv.matches("Downstairs TV"));
+    /// ```
+    pub fn matches(&self, identifier: &str) -> bool {
+        let identifier = identifier.trim();
+        let mac_matches = self
+            .mac
+            .as_ref()
+            .map_or(false, |mac| normalize_mac(mac) == normalize_mac(identifier));
+        let name_matches = self
+            .friendly_name
+            .as_ref()
+            .map_or(false, |name| name.trim().eq_ignore_ascii_case(identifier));
+        mac_matches || name_matches
+    }
+}
+
+/// Writes a MAC address in lower case with `:` separators.
+fn normalize_mac(mac: &str) -> String {
+    mac.trim().to_lowercase().replace('-', ":")
+}
+
+/// Finds the LG webOS TV's on the LAN with an SSDP search.
+/// # Example
+///```
+/// use aa_models::tv;
+///
+/// for tv in tv::discover_tvs(tv::DISCOVERY_TIMEOUT) {
+///     println!("{:?} is at {}", tv.friendly_name, tv.ip);
+/// }
+/// ```
+/// # Param
+/// * `timeout` How long to listen for TV's to answer.
+/// # Return
+/// Every TV that answered, once each, or nothing if the search could not be sent.
+pub fn discover_tvs(timeout: Duration) -> Vec<DiscoveredTv> {
+    let socket = match UdpSocket::bind("0.0.0.0:0") {
+        Ok(socket) => socket,
+        Err(e) => {
+            debug!("Could not open a socket for SSDP: {}", e);
+            return vec![];
+        }
+    };
+    let search = format!(
+        "M-SEARCH * HTTP/1.1\r\nHOST: {}\r\nMAN: \"ssdp:discover\"\r\nMX: {}\r\nST: {}\r\n\r\n",
+        SSDP_ADDRESS,
+        timeout.as_secs().max(1),
+        WEBOS_SEARCH_TARGET
+    );
+    if let Err(e) = socket.send_to(search.as_bytes(), SSDP_ADDRESS) {
+        debug!("Could not send the SSDP search: {}", e);
+        return vec![];
+    }
+
+    let started = Instant::now();
+    let mut answers: Vec<(String, String, String)> = vec![];
+    let mut buffer = [0u8; 2048];
+    while let Some(remaining) = timeout.checked_sub(started.elapsed()) {
+        if remaining.is_zero() || socket.set_read_timeout(Some(remaining)).is_err() {
+            break;
+        }
+        let (length, from) = match socket.recv_from(&mut buffer) {
+            Ok(answer) => answer,
+            Err(..) => break,
+        };
+        let ip = from.ip().to_string();
+        if answers.iter().any(|(known_ip, _, _)| *known_ip == ip) {
+            continue;
+        }
+        if let Some((location, usn)) = parse_ssdp_response(&String::from_utf8_lossy(&buffer[..length])) {
+            answers.push((ip, location, usn));
+        }
+    }
+
+    let arp_table = fs::read_to_string("/proc/net/arp").unwrap_or_default();
+    answers
+        .into_iter()
+        .map(|(ip, location, usn)| DiscoveredTv {
+            friendly_name: fetch_friendly_name(&location),
+            mac: mac_from_arp_table(&arp_table, &ip),
+            ip,
+            usn,
+        })
+        .collect()
+}
+
+/// Finds the IP the given TV currently has on the LAN, for TV's whose stored IP went stale after DHCP gave them a new
+/// one. The TV is found by its MAC address, or by its name when it has no MAC address. When it has neither and only
+/// one TV answers, that TV is taken.
+/// # Example
+///```
+/// use aa_models::device;
+/// use aa_models::tv;
+///
+/// let device = device::get_device_from_guid(&String::from("test_tv"));
+/// if let Some(ip) = tv::find_tv_ip(&device) {
+///     println!("The TV is at {}", ip);
+/// }
+/// ```
+/// # Param
+/// * `dev` The TV to find.
+/// # Return
+/// The IP of the TV, or None if it did not answer (or several TV's answered and none of them matched).
+pub fn find_tv_ip(dev: &Device) -> Option<String> {
+    let found = discover_tvs(DISCOVERY_TIMEOUT);
+    let identifier = match &dev.mac {
+        Some(mac) => mac.as_str(),
+        None if !dev.name.trim().is_empty() => dev.name.as_str(),
+        None if found.len() == 1 => return found.into_iter().next().map(|tv| tv.ip),
+        None => return None,
+    };
+    found.into_iter().find(|tv| tv.matches(identifier)).map(|tv| tv.ip)
+}
+
+/// Reads the description url and unique service name out of the answer to an SSDP search.
+/// # Return
+/// The `LOCATION` and `USN` headers, or None if the answer is not from a webOS TV.
+fn parse_ssdp_response(response: &str) -> Option<(String, String)> {
+    let mut location = None;
+    let mut usn = None;
+    let mut is_webos = false;
+    for line in response.lines() {
+        let (name, value) = match line.split_once(':') {
+            Some(header) => header,
+            None => continue,
         };
+        let value = value.trim().to_string();
+        match name.trim().to_ascii_uppercase().as_str() {
+            "LOCATION" => location = Some(value),
+            "USN" => usn = Some(value),
+            "ST" => is_webos = value == WEBOS_SEARCH_TARGET,
+            _ => {}
+        }
     }
-    TvState::default()
-}
\ No newline at end of file
+    if !is_webos {
+        return None;
+    }
+    Some((location?, usn?))
+}
+
+/// Reads the friendly name of a TV out of its UPnP description.
+fn fetch_friendly_name(location: &str) -> Option<String> {
+    let mut response = Request::get(location)
+        .timeout(DISCOVERY_TIMEOUT)
+        .body(())
+        .ok()?
+        .send()
+        .ok()?;
+    let description = response.text().ok()?;
+    let start = description.find("<friendlyName>")? + "<friendlyName>".len();
+    let end = start + description[start..].find("</friendlyName>")?;
+    Some(description[start..end].trim().to_string())
+}
+
+/// Finds the MAC address of the given IP in the contents of `/proc/net/arp`.
+fn mac_from_arp_table(arp_table: &str, ip: &str) -> Option<String> {
+    arp_table
+        .lines()
+        .skip(1)
+        .map(|line| line.split_whitespace().collect::<Vec<&str>>())
+        .find(|columns| columns.first() == Some(&ip))
+        .and_then(|columns| columns.get(3).map(|mac| mac.to_string()))
+        .filter(|mac| mac != "00:00:00:00:00:00")
+}